@@ -1,11 +1,22 @@
 use anchor_lang::prelude::*;
 use std::mem::size_of;
-use anchor_lang::solana_program::{clock::Clock, program::invoke, system_instruction};
+use anchor_lang::solana_program::{clock::Clock, hash::{hash, hashv}, program::invoke, system_instruction, sysvar};
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+use anchor_spl::token_2022_extensions::transfer_fee::{harvest_withheld_tokens_to_mint, HarvestWithheldTokensToMint};
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
 
 declare_id!("GUXx1x2kMBxJwLmyxWJMaWAqMhJHx7zabDqHdv7AFFLE");
 
-// Define platform fee (5%)
-const PLATFORM_FEE_PERCENT: u8 = 5;
+// Basis points in 100%
+const BPS_DENOMINATOR: u16 = 10_000;
+
+// Platform fee (5%) for raffles created before fees were stored per raffle
+const LEGACY_FEE_BPS: u16 = 500;
 
 // Maximum number of tickets for bitmap size calculation
 const MAX_BITMAP_SIZE: usize = 10000;
@@ -13,14 +24,58 @@ const MAX_BITMAP_SIZE: usize = 10000;
 // Number of bits in a byte for bit packing
 const BITS_PER_BYTE: usize = 8;
 
+// Number of purchase entries stored in each of a buyer's ticket ledger chunks
+const LEDGER_CHUNK_CAPACITY: u32 = 8;
+
+// How long a commit-reveal creator has to reveal after the raffle ends before refunds open (24 hours)
+const REVEAL_GRACE_PERIOD: i64 = 24 * 60 * 60;
+
+// Slots between a reveal request and the slot whose hash is mixed into the draw
+const REVEAL_SLOT_DELAY: u64 = 10;
+
+// Anchor discriminator of an oracle `OracleRandomness` account: sha256("account:OracleRandomness")[..8]
+const ORACLE_RANDOMNESS_DISCRIMINATOR: [u8; 8] = [92, 132, 25, 204, 98, 44, 217, 191];
+
+// Most winners (prize tiers) a raffle can have
+const MAX_WINNERS: usize = 10;
+
+// Longest ticker accepted in the mint symbol registry
+const MAX_SYMBOL_LEN: usize = 10;
+
+// Longest raffle name and description, in bytes
+const MAX_NAME_LEN: usize = 64;
+const MAX_DESCRIPTION_LEN: usize = 256;
+
+// Longest raffle ID: "7F-", a symbol, "-" and the raffle number (up to 10 digits)
+const MAX_RAFFLE_ID_LEN: usize = 3 + MAX_SYMBOL_LEN + 1 + 10;
+
+// Token-2022 mint extensions that cannot move the vault's tokens or block its transfers. Anything
+// else (transfer hooks, permanent delegates, default-frozen accounts, ...) is rejected.
+const SUPPORTED_MINT_EXTENSIONS: [ExtensionType; 8] = [
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::MintCloseAuthority,
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+    ExtensionType::GroupPointer,
+    ExtensionType::TokenGroup,
+    ExtensionType::GroupMemberPointer,
+];
+
+// Current UserStats layout version
+const USER_STATS_VERSION: u8 = 1;
+
+// Seconds in a day
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Stored week/month numbers below this predate calendar periods (yyyyww/yyyymm are far larger)
+const LEGACY_PERIOD_LIMIT: u32 = 100_000;
+
 // Number of top players for weekly and monthly leaderboards
-#[allow(dead_code)]
 const WEEKLY_TOP_PLAYERS: usize = 50;
-#[allow(dead_code)]
 const MONTHLY_TOP_PLAYERS: usize = 100;
 
 // Prize distribution percentages for monthly winners (first, second, third place)
-#[allow(dead_code)]
 const MONTHLY_PRIZE_DISTRIBUTION: [u8; 3] = [60, 30, 10];
 
 #[program]
@@ -37,347 +92,955 @@ pub mod basic {
         Ok(())
     }
 
-    // Initialize a new raffle
-    pub fn initialize_raffle(
-        ctx: Context<InitializeRaffle>,
-        name: String,
-        description: String,
-        ticket_price: u64,
-        duration_hours: u64,
-        max_tickets: u32,
+    // Initialize the platform configuration (program admin only)
+    pub fn initialize_platform_config(
+        ctx: Context<InitializePlatformConfig>,
+        treasury: Pubkey,
+        default_fee_bps: u16,
+        max_fee_bps: u16,
+        weekly_pool_bps: u16,
+        monthly_pool_bps: u16,
     ) -> Result<()> {
-        let raffle = &mut ctx.accounts.raffle;
-        let counter = &mut ctx.accounts.program_counter;
+        let config = &mut ctx.accounts.platform_config;
         let authority = &ctx.accounts.authority;
-        let clock = Clock::get()?;
-        
-        // Validate inputs
-        require!(duration_hours > 0, RaffleError::InvalidDuration);
-        require!(duration_hours <= 30 * 24, RaffleError::DurationTooLong); // Max 30 days
-        require!(max_tickets > 0, RaffleError::InvalidTicketCount);
-        require!(ticket_price > 0, RaffleError::InvalidTicketPrice);
-        require!(max_tickets <= MAX_BITMAP_SIZE as u32, RaffleError::TooManyTickets);
-        
-        // Increment counter and generate unique ID
-        counter.raffle_count += 1;
-        let id_number = format!("{:03}", counter.raffle_count);
-        let raffle_id = format!("7F-SOL-{}", id_number);
-        
-        // Set up raffle parameters
-        raffle.authority = authority.key();
-        raffle.name = name.clone();
-        raffle.description = description;
-        raffle.ticket_price = ticket_price;
-        raffle.start_timestamp = clock.unix_timestamp;
-        raffle.end_timestamp = clock.unix_timestamp + (duration_hours * 3600) as i64; // Convert hours to seconds
-        raffle.max_tickets = max_tickets;
-        raffle.total_tickets = 0;
-        raffle.is_active = true;
-        raffle.winner = None;
-        raffle.raffle_id = raffle_id.clone();
-        
-        // Initialize the bitmap for tracking used ticket numbers using bit packing (8 tickets per byte)
-        // Calculate how many bytes we need: ceiling of max_tickets / 8
-        let bytes_needed = (max_tickets as usize + BITS_PER_BYTE - 1) / BITS_PER_BYTE;
-        raffle.used_numbers = vec![0u8; bytes_needed];
-        
-        msg!("Raffle '{}' (ID: {}) initialized with ticket price: {} SOL", 
-             raffle.name, raffle_id, ticket_price / 1_000_000_000);
-        msg!("Platform fee: {}%", PLATFORM_FEE_PERCENT);
-        
+
+        // Only the program admin recorded in the counter can set up the platform
+        require!(ctx.accounts.program_counter.authority == authority.key(), RaffleError::UnauthorizedAccess);
+        require!(max_fee_bps <= BPS_DENOMINATOR, RaffleError::InvalidFee);
+        require!(default_fee_bps <= max_fee_bps, RaffleError::InvalidFee);
+        require!(weekly_pool_bps as u32 + monthly_pool_bps as u32 <= BPS_DENOMINATOR as u32, RaffleError::InvalidFee);
+
+        config.admin = authority.key();
+        config.treasury = treasury;
+        config.default_fee_bps = default_fee_bps;
+        config.max_fee_bps = max_fee_bps;
+        config.weekly_pool_bps = weekly_pool_bps;
+        config.monthly_pool_bps = monthly_pool_bps;
+
+        msg!("Platform config initialized: treasury {}, fee {} bps (cap {} bps)",
+             treasury, default_fee_bps, max_fee_bps);
+        msg!("Leaderboard pools get {} bps (weekly) and {} bps (monthly) of fees", weekly_pool_bps, monthly_pool_bps);
         Ok(())
     }
 
-    // Buy a ticket for a raffle
-    pub fn buy_ticket(ctx: Context<BuyTicket>, _bump: u8) -> Result<()> {
-        let raffle = &mut ctx.accounts.raffle;
-        let buyer = &ctx.accounts.buyer;
-        let ticket = &mut ctx.accounts.ticket;
-        let user_stats = &mut ctx.accounts.user_stats;
-        let participant_flag = &mut ctx.accounts.participant_flag;
-        let clock = Clock::get()?;
-        
-        // Check if raffle is active and not ended
-        require!(raffle.is_active, RaffleError::RaffleNotActive);
-        require!(clock.unix_timestamp < raffle.end_timestamp, RaffleError::RaffleEnded);
-        require!(raffle.total_tickets < raffle.max_tickets, RaffleError::RaffleFull);
-        
-        // Transfer SOL from buyer to raffle account
-        invoke(
-            &system_instruction::transfer(
-                buyer.key,
-                raffle.to_account_info().key,
-                raffle.ticket_price,
-            ),
-            &[
-                buyer.to_account_info(),
-                raffle.to_account_info(),
-            ],
-        )?;
-        
-        // Track unique participants
-        if participant_flag.raffle.to_bytes() == [0; 32] {
-            // Initialize the participant flag
-            participant_flag.raffle = raffle.key();
-            participant_flag.participant = buyer.key();
-            
-            // Increment unique entrants counter
-            raffle.unique_entrants += 1;
-            msg!("New unique entrant: {}. Total unique entrants: {}", buyer.key(), raffle.unique_entrants);
+    // Update the platform admin, fee treasury, default fee, fee cap or leaderboard pool shares
+    // (platform admin only). Raffles keep the fee they were created with.
+    pub fn update_platform_config(
+        ctx: Context<UpdatePlatformConfig>,
+        admin: Option<Pubkey>,
+        treasury: Option<Pubkey>,
+        default_fee_bps: Option<u16>,
+        max_fee_bps: Option<u16>,
+        weekly_pool_bps: Option<u16>,
+        monthly_pool_bps: Option<u16>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.platform_config;
+
+        require!(config.admin == ctx.accounts.admin.key(), RaffleError::UnauthorizedAccess);
+
+        if let Some(admin) = admin {
+            config.admin = admin;
         }
-        
-        // Update user statistics for leaderboard
-        user_stats.total_tickets_purchased += 1;
-        
-        // Update current week and month statistics
-        let current_week = get_week_number(clock.unix_timestamp);
-        let current_month = get_month_number(clock.unix_timestamp);
-        
-        if user_stats.current_week != current_week {
-            user_stats.current_week = current_week;
-            user_stats.weekly_tickets = 0;
+        if let Some(treasury) = treasury {
+            config.treasury = treasury;
         }
-        
-        if user_stats.current_month != current_month {
-            user_stats.current_month = current_month;
-            user_stats.monthly_tickets = 0;
+        if let Some(max_fee_bps) = max_fee_bps {
+            require!(max_fee_bps <= BPS_DENOMINATOR, RaffleError::InvalidFee);
+            config.max_fee_bps = max_fee_bps;
         }
-        
-        user_stats.weekly_tickets += 1;
-        user_stats.monthly_tickets += 1;
-        
-        // Generate a random ticket number
-        let random_seed = clock.unix_timestamp
-            .wrapping_add(clock.slot as i64)
-            .wrapping_add(buyer.key().to_bytes()[0] as i64);
-        
-        // Find an unused ticket number - starting from 1 (not 0)
-        let max_tickets = raffle.max_tickets;
-        // Generate a number between 1 and max_tickets (inclusive)
-        let mut ticket_number = ((random_seed as u64 % max_tickets as u64) as u32) + 1;
-        
-        // Linear probing to find unused number
-        let start_position = ticket_number;
-        loop {
-            // Convert ticket_number (1-based) to bitmap index (0-based)
-            let bitmap_index = ticket_number - 1;
-            // Check if this ticket is unused using bit operations
-            let byte_index = bitmap_index as usize / BITS_PER_BYTE;
-            let bit_index = bitmap_index as usize % BITS_PER_BYTE;
-            let bit_mask = 1u8 << bit_index;
-            
-            if (raffle.used_numbers[byte_index] & bit_mask) == 0 {
-                // Found an unused number - mark it as used
-                raffle.used_numbers[byte_index] |= bit_mask;
-                break;
-            }
-            
-            // Try the next number, ensuring we stay within range 1 to max_tickets
-            ticket_number = (ticket_number % max_tickets) + 1;
-            
-            // If we've checked all numbers, there's a bug
-            if ticket_number == start_position {
-                return Err(RaffleError::NoAvailableTickets.into());
-            }
+        if let Some(default_fee_bps) = default_fee_bps {
+            config.default_fee_bps = default_fee_bps;
         }
-        
-        // Initialize the ticket with the random number
-        ticket.buyer = buyer.key();
-        ticket.raffle = raffle.key();
-        ticket.ticket_number = ticket_number;
-        
-        // Update the raffle state
-        raffle.total_tickets += 1;
-        
-        // Check if raffle is now sold out, if so, draw winner immediately
-        if raffle.total_tickets == raffle.max_tickets {
-            msg!("Raffle sold out! Drawing winner...");
-            raffle.is_active = false;
-            
-            // Generate a pseudo-random winner from the sold tickets
-            let random_seed = clock.unix_timestamp.wrapping_add(clock.slot as i64);
-            
-            // We need to select from actual sold tickets, not just any number
-            // Randomly select a position in the used_numbers array where value is true
-            let mut available_tickets = Vec::new();
-            for i in 0..raffle.max_tickets {
-                // Convert to bitmap index (0-based)
-                let bitmap_index = i;
-                let byte_index = bitmap_index as usize / BITS_PER_BYTE;
-                let bit_index = bitmap_index as usize % BITS_PER_BYTE;
-                let bit_mask = 1u8 << bit_index;
-                
-                if (raffle.used_numbers[byte_index] & bit_mask) != 0 {
-                    // Push ticket number (1-based)
-                    available_tickets.push(i + 1);
-                }
-            }
-            
-            let winner_index = (random_seed as u64 % available_tickets.len() as u64) as usize;
-            let winner_ticket = available_tickets[winner_index];
-            
-            // Set the winner
-            raffle.winner = Some(winner_ticket);
-            
-            msg!("Winner drawn: Ticket #{}", winner_ticket);
+        if let Some(weekly_pool_bps) = weekly_pool_bps {
+            config.weekly_pool_bps = weekly_pool_bps;
+        }
+        if let Some(monthly_pool_bps) = monthly_pool_bps {
+            config.monthly_pool_bps = monthly_pool_bps;
+        }
+
+        // The default may never exceed the cap, whichever of the two changed
+        require!(config.default_fee_bps <= config.max_fee_bps, RaffleError::InvalidFee);
+        require!(
+            config.weekly_pool_bps as u32 + config.monthly_pool_bps as u32 <= BPS_DENOMINATOR as u32,
+            RaffleError::InvalidFee
+        );
+
+        msg!("Platform config updated: admin {}, treasury {}, fee {} bps (cap {} bps)",
+             config.admin, config.treasury, config.default_fee_bps, config.max_fee_bps);
+        msg!("Leaderboard pools get {} bps (weekly) and {} bps (monthly) of fees",
+             config.weekly_pool_bps, config.monthly_pool_bps);
+        Ok(())
+    }
+
+    // Initialize the randomness oracle configuration (program admin only)
+    pub fn initialize_oracle_config(ctx: Context<InitializeOracleConfig>, oracle_program: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.oracle_config;
+        let authority = &ctx.accounts.authority;
+
+        // Only the program admin recorded in the counter can configure the oracle
+        require!(ctx.accounts.program_counter.authority == authority.key(), RaffleError::UnauthorizedAccess);
+
+        config.admin = authority.key();
+        config.oracle_program = oracle_program;
+
+        msg!("Oracle config initialized with oracle program {}", oracle_program);
+        Ok(())
+    }
+
+    // Point draws at a different randomness oracle program (e.g. a mock oracle on localnet)
+    pub fn set_oracle_program(ctx: Context<SetOracleProgram>, oracle_program: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.oracle_config;
+
+        require!(config.admin == ctx.accounts.admin.key(), RaffleError::UnauthorizedAccess);
+
+        config.oracle_program = oracle_program;
+
+        msg!("Oracle program set to {}", oracle_program);
+        Ok(())
+    }
+
+    // Initialize a new raffle
+    pub fn initialize_raffle(ctx: Context<InitializeRaffle>, params: RaffleParams) -> Result<()> {
+        let ticket_price = params.ticket_price;
+        let fixed_prize = params.fixed_prize;
+        set_up_raffle(
+            &mut ctx.accounts.raffle,
+            &mut ctx.accounts.program_counter,
+            &ctx.accounts.platform_config,
+            ctx.accounts.authority.key(),
+            ctx.bumps.raffle,
+            "SOL",
+            params,
+        )?;
+
+        // A fixed prize is escrowed in the raffle account alongside the ticket sales
+        if let Some(fixed_prize) = fixed_prize {
+            require!(ctx.accounts.prize_mint.is_none(), RaffleError::InvalidPrizeConfig);
+            let raffle = &ctx.accounts.raffle;
+            let escrowed = collect_payment(raffle, &ctx.accounts.authority, None, None, None, None, fixed_prize)?;
+            ctx.accounts.raffle.prize_amount = escrowed;
+        }
+
+        // Passing a prize mint turns the raffle into an NFT raffle: the NFT is escrowed now and
+        // the ticket proceeds go to the creator instead of the winner
+        if let Some(prize_mint) = ctx.accounts.prize_mint.as_ref() {
+            let (Some(creator_prize_account), Some(prize_vault), Some(token_program)) = (
+                ctx.accounts.creator_prize_account.as_ref(),
+                ctx.accounts.prize_vault.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+            ) else {
+                return err!(RaffleError::MissingTokenAccounts);
+            };
+            require!(prize_mint.supply == 1 && prize_mint.decimals == 0, RaffleError::NotAnNft);
+            // A single NFT can only have a single winner
+            require!(ctx.accounts.raffle.prize_tiers().len() == 1, RaffleError::InvalidPrizeConfig);
+            require_supported_mint(&prize_mint.to_account_info())?;
+
+            token_interface::transfer_checked(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    TransferChecked {
+                        from: creator_prize_account.to_account_info(),
+                        mint: prize_mint.to_account_info(),
+                        to: prize_vault.to_account_info(),
+                        authority: ctx.accounts.authority.to_account_info(),
+                    },
+                ),
+                1,
+                0,
+            )?;
+
+            let raffle = &mut ctx.accounts.raffle;
+            raffle.prize = PrizeKind::Nft;
+            raffle.prize_mint = prize_mint.key();
+            raffle.prize_amount = 1;
+            msg!("NFT {} escrowed as the prize", raffle.prize_mint);
         }
+
+        let raffle = &ctx.accounts.raffle;
+        msg!("Raffle '{}' (ID: {}) initialized with ticket price: {} SOL", 
+             raffle.name, raffle.raffle_id, ticket_price / 1_000_000_000);
         
-        msg!("Ticket #{} purchased by: {}", ticket_number, buyer.key());
         Ok(())
     }
 
-    // Draw a winner for the raffle when time expires
-    pub fn draw_winner(ctx: Context<DrawWinner>) -> Result<()> {
+    // Initialize a raffle whose tickets are paid in an SPL or Token-2022 token. The mint needs a
+    // registered symbol, which also goes into the raffle ID (e.g. "7F-USDC-012").
+    pub fn initialize_token_raffle(ctx: Context<InitializeTokenRaffle>, params: RaffleParams) -> Result<()> {
+        let ticket_price = params.ticket_price;
+        let fixed_prize = params.fixed_prize;
+        require_supported_mint(&ctx.accounts.mint.to_account_info())?;
+        set_up_raffle(
+            &mut ctx.accounts.raffle,
+            &mut ctx.accounts.program_counter,
+            &ctx.accounts.platform_config,
+            ctx.accounts.authority.key(),
+            ctx.bumps.raffle,
+            &ctx.accounts.mint_symbol.symbol,
+            params,
+        )?;
+
+        ctx.accounts.raffle.mint = ctx.accounts.mint.key();
+
+        // A fixed prize is escrowed in the vault; the pot only counts ticket sales
+        if let Some(fixed_prize) = fixed_prize {
+            let escrowed = collect_payment(
+                &ctx.accounts.raffle,
+                &ctx.accounts.authority,
+                ctx.accounts.creator_token_account.as_ref(),
+                Some(&mut ctx.accounts.vault),
+                Some(&ctx.accounts.mint),
+                Some(&ctx.accounts.token_program),
+                fixed_prize,
+            )?;
+            ctx.accounts.raffle.prize_amount = escrowed;
+        }
+
+        let raffle = &ctx.accounts.raffle;
+        msg!("Raffle '{}' (ID: {}) initialized with ticket price: {} base units of {}",
+             raffle.name, raffle.raffle_id, ticket_price, raffle.mint);
+        msg!("Pot held in vault {}", ctx.accounts.vault.key());
+        Ok(())
+    }
+
+    // Register (or correct) the ticker used in the IDs of raffles priced in a mint (platform admin only)
+    pub fn register_mint_symbol(ctx: Context<RegisterMintSymbol>, symbol: String) -> Result<()> {
+        let mint_symbol = &mut ctx.accounts.mint_symbol;
+
+        require!(ctx.accounts.platform_config.admin == ctx.accounts.admin.key(), RaffleError::UnauthorizedAccess);
+        require!(is_valid_symbol(&symbol), RaffleError::InvalidSymbol);
+
+        mint_symbol.mint = ctx.accounts.mint.key();
+        mint_symbol.symbol = symbol;
+
+        msg!("Mint {} registered as {}", mint_symbol.mint, mint_symbol.symbol);
+        Ok(())
+    }
+
+    // Buy a ticket for a raffle, recorded in the buyer's ledger chunk `chunk_index`. A buyer's
+    // user stats or participant flag still in an older layout fail to load (AccountDidNotDeserialize
+    // on that account) until upgrade_user_stats / upgrade_participant_flag has grown them, which
+    // clients can do in the same transaction before the purchase.
+    pub fn buy_ticket(ctx: Context<BuyTicket>, chunk_index: u32) -> Result<()> {
+        purchase_tickets(ctx, chunk_index, 1)
+    }
+
+    // Buy a batch of consecutive tickets with a single payment and a single ledger entry. Older
+    // user stats and participant flags must be upgraded first, as for buy_ticket.
+    pub fn buy_tickets(ctx: Context<BuyTicket>, chunk_index: u32, quantity: u32) -> Result<()> {
+        purchase_tickets(ctx, chunk_index, quantity)
+    }
+
+    // Move a legacy one-per-ticket account into its buyer's ledger chunk and return its rent
+    pub fn migrate_legacy_ticket(ctx: Context<MigrateLegacyTicket>, chunk_index: u32) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let buyer = &ctx.accounts.buyer;
+        let ticket = &ctx.accounts.ticket;
+        let ticket_ledger = &mut ctx.accounts.ticket_ledger;
+
+        ticket_ledger.set_up(raffle.key(), buyer.key(), chunk_index);
+        add_legacy_ticket(ticket_ledger, raffle, ticket)?;
+
+        msg!("Legacy ticket #{} migrated to ledger chunk {} of {}", ticket.ticket_number, chunk_index, buyer.key());
+        Ok(())
+    }
+
+    // Request oracle randomness for the draw once the raffle has ended or sold out
+    pub fn request_randomness(ctx: Context<RequestRandomness>) -> Result<()> {
         let raffle = &mut ctx.accounts.raffle;
         let authority = &ctx.accounts.authority;
+        let randomness_account = &ctx.accounts.randomness_account;
         let clock = Clock::get()?;
-        
-        // Only the raffle creator can draw a winner
+
+        // Only the raffle creator can request the draw
         require!(raffle.authority == authority.key(), RaffleError::UnauthorizedAccess);
-        
-        // Check if raffle has ended
-        require!(clock.unix_timestamp >= raffle.end_timestamp, RaffleError::RaffleNotEnded);
-        
+
+        // Check if raffle has ended (or sold out)
+        require!(is_ready_to_draw(raffle, clock.unix_timestamp), RaffleError::RaffleNotEnded);
+
+        require!(raffle.draw_mode == DrawMode::Oracle, RaffleError::WrongDrawMode);
+        raffle.require_status(RaffleAction::RequestDraw)?;
+
         // Check if tickets were sold
         require!(raffle.total_tickets > 0, RaffleError::NoTicketsSold);
-        
-        // Generate a pseudo-random winner from the sold tickets
-        let random_seed = clock.unix_timestamp.wrapping_add(clock.slot as i64);
-        
-        // We need to select from actual sold tickets, not just any number
-        // Collect all purchased ticket numbers
-        let mut available_tickets = Vec::new();
-        for i in 0..raffle.max_tickets {
-            // Convert to bitmap index (0-based)
-            let bitmap_index = i;
-            let byte_index = bitmap_index as usize / BITS_PER_BYTE;
-            let bit_index = bitmap_index as usize % BITS_PER_BYTE;
-            let bit_mask = 1u8 << bit_index;
-            
-            if byte_index < raffle.used_numbers.len() && (raffle.used_numbers[byte_index] & bit_mask) != 0 {
-                // Push ticket number (1-based)
-                available_tickets.push(i + 1);
+        require!(raffle.total_tickets >= raffle.min_tickets, RaffleError::BelowMinimumTickets);
+        require!(raffle.winner.is_none(), RaffleError::WinnerAlreadyDrawn);
+
+        // A raffle gets exactly one request so the creator cannot re-roll an unwanted result
+        require!(raffle.randomness_account == Pubkey::default(), RaffleError::RandomnessAlreadyRequested);
+
+        // Remember the oracle round at request time; only the next round counts as fulfilment
+        let randomness = OracleRandomness::try_read(randomness_account)?;
+
+        raffle.randomness_account = randomness_account.key();
+        raffle.randomness_round = randomness.round;
+        raffle.randomness_request_slot = clock.slot;
+
+        // No more sales once the draw has been requested
+        raffle.is_active = false;
+
+        msg!("Randomness requested for raffle '{}' from {} at round {}",
+             raffle.name, randomness_account.key(), randomness.round);
+        Ok(())
+    }
+
+    // Draw the winners using the fulfilled oracle randomness (callable by anyone, so the creator
+    // cannot hold the draw back until a round they like comes up)
+    pub fn draw_winner(ctx: Context<DrawWinner>) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+
+        require!(raffle.draw_mode == DrawMode::Oracle, RaffleError::WrongDrawMode);
+        require!(raffle.winner.is_none(), RaffleError::WinnerAlreadyDrawn);
+        raffle.require_status(RaffleAction::Draw)?;
+        require!(raffle.randomness_account != Pubkey::default(), RaffleError::RandomnessNotRequested);
+
+        // Only the first round fulfilled after our request counts
+        let randomness = OracleRandomness::try_read(&ctx.accounts.randomness_account)?;
+        check_fulfilment(raffle.randomness_round, randomness.round)?;
+
+        // Map the randomness onto the sold tickets in ticket-number order and store the proof
+        record_draw(raffle, randomness.value)?;
+
+        msg!("Winners drawn for raffle '{}': tickets {:?} (first place index {} of {} sold)",
+             raffle.name, raffle.winners, raffle.winning_index.unwrap_or_default(), raffle.total_tickets);
+
+        Ok(())
+    }
+
+    // Fix the future slot whose hash will be mixed into a commit-reveal draw
+    pub fn request_reveal(ctx: Context<RequestReveal>) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let authority = &ctx.accounts.authority;
+        let clock = Clock::get()?;
+
+        require!(raffle.authority == authority.key(), RaffleError::UnauthorizedAccess);
+        require!(raffle.draw_mode == DrawMode::CommitReveal, RaffleError::WrongDrawMode);
+        require!(is_ready_to_draw(raffle, clock.unix_timestamp), RaffleError::RaffleNotEnded);
+        require!(clock.unix_timestamp <= raffle.end_timestamp + REVEAL_GRACE_PERIOD, RaffleError::RevealWindowClosed);
+        require!(raffle.total_tickets > 0, RaffleError::NoTicketsSold);
+        require!(raffle.total_tickets >= raffle.min_tickets, RaffleError::BelowMinimumTickets);
+        raffle.require_status(RaffleAction::RequestDraw)?;
+        require!(raffle.winner.is_none(), RaffleError::WinnerAlreadyDrawn);
+
+        // One request per raffle, otherwise the creator could keep picking new slots
+        require!(raffle.randomness_request_slot == 0, RaffleError::RandomnessAlreadyRequested);
+
+        raffle.randomness_request_slot = clock.slot;
+        raffle.is_active = false;
+
+        msg!("Reveal requested for raffle '{}'; reveal after slot {}",
+             raffle.name, clock.slot + REVEAL_SLOT_DELAY);
+        Ok(())
+    }
+
+    // Reveal the committed secret and draw the winner from it and the locked slot hash
+    pub fn reveal_winner(ctx: Context<RevealWinner>, secret: [u8; 32]) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let authority = &ctx.accounts.authority;
+        let clock = Clock::get()?;
+
+        require!(raffle.authority == authority.key(), RaffleError::UnauthorizedAccess);
+        require!(raffle.draw_mode == DrawMode::CommitReveal, RaffleError::WrongDrawMode);
+        require!(raffle.winner.is_none(), RaffleError::WinnerAlreadyDrawn);
+        raffle.require_status(RaffleAction::Draw)?;
+        require!(raffle.randomness_request_slot != 0, RaffleError::RandomnessNotRequested);
+        require!(clock.unix_timestamp <= raffle.end_timestamp + REVEAL_GRACE_PERIOD, RaffleError::RevealWindowClosed);
+
+        // The secret must match what was committed at creation
+        require!(hash(&secret).to_bytes() == raffle.commitment, RaffleError::InvalidCommitment);
+
+        // The slot hash was unknown to everyone when the reveal was requested
+        let target_slot = raffle.randomness_request_slot + REVEAL_SLOT_DELAY;
+        require!(clock.slot > target_slot, RaffleError::RandomnessNotFulfilled);
+        let slot_hash = find_slot_hash(&ctx.accounts.slot_hashes.try_borrow_data()?, target_slot)?;
+
+        let randomness = hashv(&[&secret, &slot_hash, raffle.key().as_ref()]).to_bytes();
+        record_draw(raffle, randomness)?;
+
+        msg!("Secret revealed for raffle '{}': tickets {:?} (first place index {} of {} sold)",
+             raffle.name, raffle.winners, raffle.winning_index.unwrap_or_default(), raffle.total_tickets);
+        Ok(())
+    }
+
+    // Open refunds for a raffle that ended below its minimum ticket count, or for a raffle that
+    // was not drawn within the grace period: a commit-reveal creator never revealed, or the oracle
+    // round after the request was missed (callable by anyone)
+    pub fn open_refunds(ctx: Context<OpenRefunds>) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let clock = Clock::get()?;
+
+        require!(raffle.winner.is_none(), RaffleError::WinnerAlreadyDrawn);
+        require!(raffle.total_tickets > 0, RaffleError::NoTicketsSold);
+
+        if clock.unix_timestamp >= raffle.end_timestamp && raffle.total_tickets < raffle.min_tickets {
+            raffle.set_status(RaffleStatus::Refunding)?;
+
+            msg!("Raffle '{}' ended with {} of {} minimum tickets; refunds open",
+                 raffle.name, raffle.total_tickets, raffle.min_tickets);
+            return Ok(());
+        }
+
+        require!(clock.unix_timestamp > raffle.end_timestamp + REVEAL_GRACE_PERIOD, RaffleError::RevealWindowOpen);
+
+        raffle.set_status(RaffleStatus::Refunding)?;
+
+        msg!("Raffle was not drawn in time; refunds open for raffle '{}'", raffle.name);
+        Ok(())
+    }
+
+    // Refund one ledger entry of a refunding raffle; the entry is flagged so it can only be refunded once
+    pub fn claim_refund(ctx: Context<ClaimRefund>, _chunk_index: u32, entry_index: u32) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let buyer = &ctx.accounts.buyer;
+        let ticket_ledger = &mut ctx.accounts.ticket_ledger;
+        let vault = ctx.accounts.vault.as_ref();
+        let mint = ctx.accounts.mint.as_ref();
+        let token_program = ctx.accounts.token_program.as_ref();
+
+        raffle.require_status(RaffleAction::Refund)?;
+
+        let entry = ticket_ledger.entries.get_mut(entry_index as usize)
+            .ok_or(RaffleError::InvalidLedgerIndex)?;
+        require!(entry.buyer == buyer.key(), RaffleError::NotTicketOwner);
+
+        let recipient = payment_recipient(raffle, &buyer.to_account_info(), ctx.accounts.buyer_token_account.as_ref())?;
+        let refund = take_refund(entry)?;
+        pay_from_pot(raffle, vault, mint, token_program, &recipient, refund)?;
+        raffle.refunded_tickets += entry.count;
+
+        msg!("{} ticket(s) from #{} refunded {} to {}",
+             entry.count, entry.first_number, refund, buyer.key());
+        Ok(())
+    }
+
+    // Push refunds to buyers in batches (callable by anyone). Remaining accounts are
+    // (ticket ledger chunk, buyer wallet) pairs, or (ticket ledger chunk, buyer token account)
+    // pairs for token raffles; entries already refunded are skipped.
+    pub fn process_refunds<'info>(ctx: Context<'_, '_, 'info, 'info, ProcessRefunds<'info>>) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let vault = ctx.accounts.vault.as_ref();
+        let mint = ctx.accounts.mint.as_ref();
+        let token_program = ctx.accounts.token_program.as_ref();
+        let raffle_key = raffle.key();
+
+        raffle.require_status(RaffleAction::Refund)?;
+
+        let mut refunded_entries = 0u32;
+        let mut refunded_amount = 0u64;
+        for (ledger_info, recipient) in refund_pairs(ctx.remaining_accounts)? {
+            let mut ticket_ledger = Account::<TicketLedger>::try_from(ledger_info)?;
+            if raffle.is_token_raffle() {
+                let token_account = InterfaceAccount::<TokenAccount>::try_from(recipient)?;
+                check_refund_recipient(raffle, raffle_key, &ticket_ledger, token_account.owner, token_account.mint)?;
+            } else {
+                check_refund_recipient(raffle, raffle_key, &ticket_ledger, recipient.key(), Pubkey::default())?;
+            }
+
+            for entry in ticket_ledger.entries.iter_mut().filter(|entry| !entry.refunded) {
+                let refund = take_refund(entry)?;
+                pay_from_pot(raffle, vault, mint, token_program, recipient, refund)?;
+                raffle.refunded_tickets += entry.count;
+                refunded_amount += refund;
+                refunded_entries += 1;
             }
+
+            // Persist the refunded flags
+            ticket_ledger.exit(&crate::ID)?;
         }
-        
-        // If there are no available tickets, return error
-        require!(!available_tickets.is_empty(), RaffleError::NoAvailableTickets);
-        
-        // Get a pseudo-random index based on the random seed
-        let selected_index = (random_seed as usize) % available_tickets.len();
-        let winning_ticket = available_tickets[selected_index];
-        
-        // Mark raffle as inactive
-        raffle.is_active = false;
-        
-        // Store the winning ticket number
-        raffle.winner = Some(winning_ticket);
-        
-        msg!("Winner drawn for raffle '{}': ticket #{}", raffle.name, winning_ticket);
-        
+
+        msg!("Refunded {} ledger entries ({} in total) for raffle '{}'",
+             refunded_entries, refunded_amount, raffle.name);
         Ok(())
     }
 
-    // Claim prize by the winner
-    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+    // Claim the prize of one place by its winner. Each winner claims independently; the platform
+    // fee (and a fixed-prize creator's share) is paid with the first claim. The winner's stats
+    // are grown to the current layout at the winner's expense if they are still in an older one.
+    pub fn claim_prize(ctx: Context<ClaimPrize>, _chunk_index: u32, place: u8) -> Result<()> {
         let raffle = &mut ctx.accounts.raffle;
         let winner = &ctx.accounts.winner;
-        let winning_ticket = &ctx.accounts.winning_ticket;
-        let authority = &ctx.accounts.authority;
+        let ticket_ledger = &ctx.accounts.ticket_ledger;
+        let fee_treasury = &ctx.accounts.fee_treasury;
+        let config = &ctx.accounts.platform_config;
+        let winner_stats = &ctx.accounts.winner_stats;
         let history = &mut ctx.accounts.raffle_history;
         let clock = Clock::get()?;
         
-        // Verify the raffle has a winner
-        let winner_ticket_number = raffle.winner.ok_or(RaffleError::NoWinnerDrawn)?;
+        // Verify the raffle has a winner and unclaimed prizes
+        raffle.require_status(RaffleAction::ClaimPrize)?;
+        // A legacy raffle that was claimed before mark_legacy_claimed ran has already paid its pot out
+        if raffle.fee_bps.is_none() {
+            let rent_exempt = Rent::get()?.minimum_balance(raffle.to_account_info().data_len());
+            let total_pot = raffle.ticket_price.checked_mul(raffle.total_tickets as u64).unwrap();
+            require!(
+                holds_legacy_pot(raffle.to_account_info().lamports(), rent_exempt, total_pot),
+                RaffleError::PrizeAlreadyClaimed
+            );
+        }
+        let winner_ticket_number = *raffle.winning_tickets().get(place as usize)
+            .ok_or(RaffleError::InvalidPlace)?;
+
+        // A second claim of the same place fails here before any lamports move
+        let place_bit = 1u16 << place;
+        require!(raffle.claimed_places & place_bit == 0, RaffleError::PrizeAlreadyClaimed);
+        let first_claim = raffle.claimed_places == 0;
+        raffle.claimed_places |= place_bit;
+
+        // Drawn -> Claimed once every place has been claimed
+        if raffle.all_prizes_claimed() {
+            raffle.set_status(RaffleStatus::Claimed)?;
+        }
         
-        // Check this is the winning ticket
-        require!(winning_ticket.ticket_number == winner_ticket_number, RaffleError::NotWinningTicket);
+        // Find the ledger entry holding the winning number in the given chunk
+        let winning_entry = ticket_ledger.find_entry(winner_ticket_number)
+            .ok_or(RaffleError::NotWinningTicket)?;
         
         // Check the caller is the ticket owner
-        require!(winning_ticket.buyer == winner.key(), RaffleError::NotTicketOwner);
+        require!(winning_entry.buyer == winner.key(), RaffleError::NotTicketOwner);
+        
+        // Split the pot using the raffle's own fee. Token raffles split what actually reached the
+        // vault, which is less than the ticket sales for mints with a transfer fee.
+        let total_pot = if raffle.is_token_raffle() {
+            raffle.pot
+        } else {
+            raffle.ticket_price.checked_mul(raffle.total_tickets as u64).unwrap()
+        };
+        let Payout { winner_amount, platform_fee } = split_pot(total_pot, raffle.fee_bps.unwrap_or(LEGACY_FEE_BPS));
+        let tiers = raffle.prize_tiers();
+        let place_share = |prize_pool: u64| split_prize_tiers(prize_pool, &tiers)[place as usize];
         
-        // Calculate prize amount (95% of pool)
-        let total_pot = raffle.ticket_price.checked_mul(raffle.total_tickets as u64).unwrap();
-        let winner_amount = total_pot.checked_mul(95).unwrap().checked_div(100).unwrap();
-        let platform_fee = total_pot.checked_mul(PLATFORM_FEE_PERCENT as u64).unwrap().checked_div(100).unwrap();
+        // Transfer prize to winner. NFT raffles hand the escrowed NFT to the winner and the
+        // ticket proceeds to the creator.
+        let vault = ctx.accounts.vault.as_ref();
+        let mint = ctx.accounts.mint.as_ref();
+        let token_program = ctx.accounts.token_program.as_ref();
+        let prize_paid = match raffle.prize {
+            PrizeKind::Pot => {
+                let prize = place_share(winner_amount);
+                let winner_recipient = payment_recipient(raffle, &winner.to_account_info(), ctx.accounts.winner_token_account.as_ref())?;
+                pay_from_pot(raffle, vault, mint, token_program, &winner_recipient, prize)?;
+                prize
+            }
+            PrizeKind::Nft => {
+                let creator = ctx.accounts.creator.as_ref().ok_or(RaffleError::MissingTokenAccounts)?;
+                let (Some(prize_vault), Some(prize_mint), Some(winner_prize_account)) = (
+                    ctx.accounts.prize_vault.as_ref(),
+                    ctx.accounts.prize_mint.as_ref(),
+                    ctx.accounts.winner_prize_account.as_ref(),
+                ) else {
+                    return err!(RaffleError::MissingTokenAccounts);
+                };
+                require!(winner_prize_account.owner == winner.key(), RaffleError::InvalidTokenAccount);
+                release_prize(raffle, prize_vault, prize_mint, token_program, winner_prize_account, creator)?;
+
+                let creator_recipient = payment_recipient(raffle, creator, ctx.accounts.creator_token_account.as_ref())?;
+                pay_from_pot(raffle, vault, mint, token_program, &creator_recipient, winner_amount)?;
+                msg!("NFT {} sent to {}; {} of proceeds paid to the creator", raffle.prize_mint, winner.key(), winner_amount);
+                0
+            }
+            PrizeKind::Fixed => {
+                // The winners share the escrowed prize plus their share of the sales after the fee
+                let SalesSplit { winner_share, creator_share } = split_sales(winner_amount, raffle.sales_share_bps);
+                let prize = place_share(raffle.prize_amount + winner_share);
+
+                let winner_recipient = payment_recipient(raffle, &winner.to_account_info(), ctx.accounts.winner_token_account.as_ref())?;
+                pay_from_pot(raffle, vault, mint, token_program, &winner_recipient, prize)?;
+                if first_claim {
+                    let creator = ctx.accounts.creator.as_ref().ok_or(RaffleError::MissingTokenAccounts)?;
+                    let creator_recipient = payment_recipient(raffle, creator, ctx.accounts.creator_token_account.as_ref())?;
+                    pay_from_pot(raffle, vault, mint, token_program, &creator_recipient, creator_share)?;
+                    msg!("{} of sales paid to the creator", creator_share);
+                }
+                prize
+            }
+        };
         
-        // Transfer prize to winner
-        **raffle.to_account_info().try_borrow_mut_lamports()? -= winner_amount;
-        **winner.to_account_info().try_borrow_mut_lamports()? += winner_amount;
+        // Transfer platform fee to the configured treasury, less the leaderboard prize pool shares.
+        // The prize pools are paid in SOL, so token raffles send their whole fee to the treasury.
+        let platform_fee = if first_claim { platform_fee } else { 0 };
+        let FeeSplit { treasury, weekly_pool, monthly_pool } = if raffle.is_token_raffle() {
+            FeeSplit { treasury: platform_fee, weekly_pool: 0, monthly_pool: 0 }
+        } else {
+            split_platform_fee(platform_fee, config.weekly_pool_bps, config.monthly_pool_bps)
+        };
+        // Only the first claim pays the fee, so later claims need no treasury token account
+        if treasury > 0 {
+            let treasury_recipient = payment_recipient(raffle, &fee_treasury.to_account_info(), ctx.accounts.treasury_token_account.as_ref())?;
+            pay_from_pot(raffle, vault, mint, token_program, &treasury_recipient, treasury)?;
+        }
+        pay_from_pot(raffle, vault, mint, token_program, &ctx.accounts.weekly_prize_vault.to_account_info(), weekly_pool)?;
+        pay_from_pot(raffle, vault, mint, token_program, &ctx.accounts.monthly_prize_vault.to_account_info(), monthly_pool)?;
         
-        // Transfer platform fee to authority
-        **raffle.to_account_info().try_borrow_mut_lamports()? -= platform_fee;
-        **authority.to_account_info().try_borrow_mut_lamports()? += platform_fee;
+        // Lifetime winnings are kept in lamports
+        let lamports_won = if raffle.is_token_raffle() { 0 } else { prize_paid };
+        // Places the wallet claimed earlier in this raffle are already in its history record
+        let first_win_in_raffle = !history.winners.iter().any(|record| record.winner == winner.key());
+        // Stats with an older layout are upgraded here rather than failing the claim
+        let mut stats = load_grown_user_stats(&winner_stats.to_account_info(), &winner.to_account_info())?;
+        stats.version = USER_STATS_VERSION;
+        record_win(&mut stats, lamports_won, first_win_in_raffle, clock.unix_timestamp);
+        stats.try_serialize(&mut &mut winner_stats.try_borrow_mut_data()?[..])?;
         
-        // Create raffle history record
-        history.raffle_id = raffle.raffle_id.clone();
-        history.raffle_name = raffle.name.clone();
-        history.creation_timestamp = raffle.start_timestamp;
-        history.end_timestamp = raffle.end_timestamp;
-        history.total_tickets_sold = raffle.total_tickets;
-        history.max_tickets = raffle.max_tickets;
-        history.final_prize_amount = winner_amount;
-        history.winner_ticket = winner_ticket_number;
-        history.winner_address = winner.key();
-        history.claim_timestamp = clock.unix_timestamp;
-        // The transaction signature will be populated by the frontend
-        history.transaction_signature = String::new();
+        // Create the raffle history record with the first claim and add every winner to it
+        let record = WinnerRecord {
+            place,
+            ticket: winner_ticket_number,
+            winner: winner.key(),
+            amount: prize_paid,
+            claim_slot: clock.slot,
+        };
+        history.record_claim(raffle, raffle.key(), record, first_claim, platform_fee, clock.unix_timestamp);
         
-        msg!("Prize of {} lamports claimed by {}", winner_amount, winner.key());
-        msg!("Platform fee of {} lamports paid to {}", platform_fee, authority.key());
+        msg!("Prize {} of {} claimed by {}", place + 1, prize_paid, winner.key());
+        msg!("Platform fee of {} paid to {}", treasury, fee_treasury.key());
+        msg!("Leaderboard pools funded: {} lamports weekly, {} lamports monthly", weekly_pool, monthly_pool);
         msg!("Raffle history record created for {}", raffle.raffle_id);
         
         Ok(())
     }
 
-    // Cancel a raffle (only possible if no tickets sold)
+    // Return an escrowed NFT to the creator of a cancelled or refunding raffle (callable by anyone)
+    pub fn return_prize(ctx: Context<ReturnPrize>) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+
+        raffle.require_status(RaffleAction::ReturnPrize)?;
+        // Fixed prizes are returned with the rest of the pot when the raffle is closed
+        require!(raffle.prize == PrizeKind::Nft && raffle.prize_amount > 0, RaffleError::NoPrizeEscrowed);
+
+        let creator = ctx.accounts.creator.to_account_info();
+        release_prize(
+            raffle,
+            &ctx.accounts.prize_vault,
+            &ctx.accounts.prize_mint,
+            Some(&ctx.accounts.token_program),
+            &ctx.accounts.creator_prize_account,
+            &creator,
+        )?;
+
+        msg!("Prize {} returned to {}", raffle.prize_mint, creator.key());
+        Ok(())
+    }
+
+    // Cancel a raffle before its draw (creator or platform admin). If tickets were sold the
+    // raffle moves to Refunding so every buyer can reclaim their ticket price.
     pub fn cancel_raffle(ctx: Context<CancelRaffle>) -> Result<()> {
         let raffle = &mut ctx.accounts.raffle;
         let authority = &ctx.accounts.authority;
         
         // Verify authority
-        require!(raffle.authority == authority.key(), RaffleError::UnauthorizedAccess);
+        let is_admin = ctx.accounts.platform_config.admin == authority.key();
+        require!(raffle.authority == authority.key() || is_admin, RaffleError::UnauthorizedAccess);
+        require!(raffle.winner.is_none(), RaffleError::WinnerAlreadyDrawn);
         
-        // Check if no tickets sold
-        require!(raffle.total_tickets == 0, RaffleError::CannotCancelActive);
+        // Once the draw has been requested the creator could see the result coming and cancel
+        // to avoid it, so from then on only the admin can cancel (e.g. for a stalled oracle)
+        require!(raffle.randomness_request_slot == 0 || is_admin, RaffleError::RandomnessAlreadyRequested);
         
-        // Mark raffle as inactive
-        raffle.is_active = false;
+        if raffle.total_tickets == 0 {
+            // Active -> Cancelled
+            raffle.set_status(RaffleStatus::Cancelled)?;
+            msg!("Raffle canceled");
+        } else {
+            // Active -> Refunding
+            raffle.set_status(RaffleStatus::Refunding)?;
+            msg!("Raffle canceled with {} tickets sold; refunds open", raffle.total_tickets);
+        }
         
-        msg!("Raffle canceled");
         Ok(())
     }
-    
-    // Initialize user stats for tracking ticket purchases and leaderboard status
-    pub fn initialize_user_stats(ctx: Context<InitializeUserStats>) -> Result<()> {
-        let user_stats = &mut ctx.accounts.user_stats;
-        let user = &ctx.accounts.user;
-        let clock = Clock::get()?;
-        
-        // Initialize user stats
-        user_stats.user = user.key();
-        user_stats.total_tickets_purchased = 0;
-        user_stats.weekly_tickets = 0;
-        user_stats.monthly_tickets = 0;
-        
-        // Get current week and month
-        // We use unix timestamp / (seconds in a day * 7) to get current week number
-        // and unix timestamp / (seconds in a day * 30) to get approximate month
-        let current_week = (clock.unix_timestamp / (86400 * 7)) as u32;
-        let current_month = (clock.unix_timestamp / (86400 * 30)) as u32;
-        
-        user_stats.current_week = current_week;
-        user_stats.current_month = current_month;
-        
-        msg!("User stats initialized for {}", user.key());
+
+    // Close a settled raffle, returning its rent and any leftover lamports to the creator
+    // (callable by anyone). A raffle with a winner can only be closed once its history is written.
+    pub fn close_raffle(ctx: Context<CloseRaffle>) -> Result<()> {
+        let raffle = &ctx.accounts.raffle;
+
+        // The history record only exists once a prize was claimed; raffles claimed before the
+        // status field existed are marked claimed by mark_legacy_claimed first
+        let history_written = ctx.accounts.raffle_history.as_ref()
+            .is_some_and(|history| history.raffle_id == raffle.raffle_id);
+
+        check_closable(raffle, history_written)?;
+
+        // Token raffles hand any dust left in the vault to the creator and close the vault
+        if raffle.is_token_raffle() {
+            let (Some(vault), Some(mint), Some(token_program)) =
+                (ctx.accounts.vault.as_ref(), ctx.accounts.mint.as_ref(), ctx.accounts.token_program.as_ref()) else {
+                return err!(RaffleError::MissingTokenAccounts);
+            };
+            let creator = ctx.accounts.creator.to_account_info();
+            let recipient = payment_recipient(raffle, &creator, ctx.accounts.creator_token_account.as_ref())?;
+            pay_from_pot(raffle, Some(vault), Some(mint), Some(token_program), &recipient, vault.amount)?;
+
+            // Transfer fees withheld in the vault block closing it; they belong to the mint anyway
+            if has_transfer_fee(&mint.to_account_info())? {
+                harvest_withheld_tokens_to_mint(
+                    CpiContext::new(
+                        token_program.to_account_info(),
+                        HarvestWithheldTokensToMint {
+                            token_program_id: token_program.to_account_info(),
+                            mint: mint.to_account_info(),
+                        },
+                    ),
+                    vec![vault.to_account_info()],
+                )?;
+            }
+
+            let index = raffle.index.to_le_bytes();
+            let bump = [raffle.bump];
+            let raffle_seeds: &[&[u8]] = &[b"raffle", &index, &bump];
+            token_interface::close_account(CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: creator,
+                    authority: raffle.to_account_info(),
+                },
+                &[raffle_seeds],
+            ))?;
+        }
+
+        msg!("Raffle {} closed; {} lamports returned to {}",
+             raffle.raffle_id, raffle.to_account_info().lamports(), ctx.accounts.creator.key());
         Ok(())
     }
-}
 
-// Program counter to track raffle IDs
-#[account]
-pub struct ProgramCounter {
-    pub authority: Pubkey,         // Program admin
+    // Close a participant flag of a settled raffle, returning its rent to the participant
+    // (callable by anyone). Flags created before holdings were tracked must be upgraded first;
+    // the upgrade's rent top-up is returned with the rest.
+    pub fn close_participant_flag(ctx: Context<CloseParticipantFlag>) -> Result<()> {
+        require_raffle_settled(&ctx.accounts.raffle)?;
+
+        msg!("Participant flag of {} closed", ctx.accounts.participant.key());
+        Ok(())
+    }
+
+    // Close a ticket ledger chunk of a settled raffle, returning its rent to the buyer (callable by anyone)
+    pub fn close_ticket_ledger(ctx: Context<CloseTicketLedger>) -> Result<()> {
+        require_raffle_settled(&ctx.accounts.raffle)?;
+
+        msg!("Ticket ledger chunk {} of {} closed", ctx.accounts.ticket_ledger.chunk_index, ctx.accounts.buyer.key());
+        Ok(())
+    }
+
+    // Close a legacy ticket of a settled raffle, returning its rent to the buyer (callable by anyone)
+    pub fn close_legacy_ticket(ctx: Context<CloseLegacyTicket>) -> Result<()> {
+        require_raffle_settled(&ctx.accounts.raffle)?;
+
+        msg!("Legacy ticket #{} of {} closed", ctx.accounts.ticket.ticket_number, ctx.accounts.buyer.key());
+        Ok(())
+    }
+    
+    // Initialize user stats for tracking ticket purchases and leaderboard status.
+    // Buying a ticket creates them on demand; this is kept for existing clients.
+    pub fn initialize_user_stats(ctx: Context<InitializeUserStats>) -> Result<()> {
+        let clock = Clock::get()?;
+        set_up_user_stats(&mut ctx.accounts.user_stats, ctx.accounts.user.key(), clock.unix_timestamp);
+        Ok(())
+    }
+
+    // Grow user stats created with an older layout to the current one (anyone can pay for it)
+    pub fn upgrade_user_stats(ctx: Context<UpgradeUserStats>) -> Result<()> {
+        let user_stats = &ctx.accounts.user_stats;
+        let payer = &ctx.accounts.payer;
+
+        let mut stats = load_grown_user_stats(&user_stats.to_account_info(), &payer.to_account_info())?;
+        let from_version = stats.version;
+        stats.version = USER_STATS_VERSION;
+        stats.try_serialize(&mut &mut user_stats.try_borrow_mut_data()?[..])?;
+
+        msg!("User stats of {} upgraded from version {} to {}", stats.user, from_version, USER_STATS_VERSION);
+        Ok(())
+    }
+
+    // Grow a raffle created with an older, shorter layout so every field added since fits (anyone
+    // can pay for it). Raffles whose original fields fill their account must be upgraded before
+    // any other instruction can load them.
+    pub fn upgrade_raffle(ctx: Context<UpgradeRaffle>) -> Result<()> {
+        let raffle = &ctx.accounts.raffle;
+
+        require!(
+            raffle.try_borrow_data()?.starts_with(Raffle::DISCRIMINATOR),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+
+        let space = Raffle::upgraded_space(&raffle.try_borrow_data()?)?;
+        grow_account(&raffle.to_account_info(), &ctx.accounts.payer.to_account_info(), space)?;
+
+        // The grown space is zeroed, which decodes as the defaults of the appended fields
+        let upgraded = Raffle::try_deserialize(&mut &raffle.try_borrow_data()?[..])?;
+
+        msg!("Raffle {} upgraded to {} bytes", upgraded.raffle_id, space);
+        Ok(())
+    }
+
+    // Mark a raffle created before the status field as claimed, using the history record its
+    // claim wrote (callable by anyone). Such raffles otherwise decode as drawn and unclaimed.
+    pub fn mark_legacy_claimed(ctx: Context<MarkLegacyClaimed>) -> Result<()> {
+        let raffle = &mut ctx.accounts.raffle;
+        let history = &ctx.accounts.legacy_history;
+
+        mark_claimed_from_history(raffle, history)?;
+
+        msg!("Raffle {} marked as claimed by {} from its history record", raffle.raffle_id, history.winner_address);
+        Ok(())
+    }
+
+    // Grow a participant flag created before holdings were tracked (anyone can pay for it)
+    pub fn upgrade_participant_flag(ctx: Context<UpgradeParticipantFlag>) -> Result<()> {
+        let participant_flag = &ctx.accounts.participant_flag;
+
+        require!(
+            participant_flag.try_borrow_data()?.starts_with(ParticipantFlag::DISCRIMINATOR),
+            anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+        );
+
+        grow_account(&participant_flag.to_account_info(), &ctx.accounts.payer.to_account_info(), ParticipantFlag::SPACE)?;
+
+        msg!("Participant flag of {} in raffle {} upgraded",
+             ctx.accounts.participant.key(), ctx.accounts.raffle.key());
+        Ok(())
+    }
+
+    // Initialize the leaderboard, its rankings and its prize vaults (platform admin only)
+    pub fn initialize_leaderboard(ctx: Context<InitializeLeaderboard>) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard;
+        let rankings = &mut ctx.accounts.rankings;
+        let admin = &ctx.accounts.admin;
+        let clock = Clock::get()?;
+
+        require!(ctx.accounts.platform_config.admin == admin.key(), RaffleError::UnauthorizedAccess);
+
+        rankings.week = get_week_number(clock.unix_timestamp);
+        rankings.month = get_month_number(clock.unix_timestamp);
+
+        leaderboard.admin = admin.key();
+
+        msg!("Leaderboard initialized for week {} and month {}", rankings.week, rankings.month);
+        Ok(())
+    }
+
+    // Copy a player's ticket counts for the running week and month from their stats into the
+    // rankings (callable by anyone). Purchases only update the player's own stats, so a player
+    // has to be submitted before the period ends to compete for its prize.
+    pub fn submit_rankings(ctx: Context<SubmitRankings>) -> Result<()> {
+        let rankings = &mut ctx.accounts.rankings;
+        let user_stats = &ctx.accounts.user_stats;
+        let clock = Clock::get()?;
+
+        let (weekly_tickets, monthly_tickets) = current_period_tickets(user_stats, clock.unix_timestamp);
+        require!(weekly_tickets > 0 || monthly_tickets > 0, RaffleError::InvalidTicketCount);
+        rankings.record(user_stats.user, weekly_tickets, monthly_tickets, clock.unix_timestamp);
+
+        msg!("Rankings of {} updated: {} tickets this week, {} this month",
+             user_stats.user, weekly_tickets, monthly_tickets);
+        Ok(())
+    }
+
+    // Deposit lamports into the weekly or monthly prize vault on top of the fee shares (anyone can fund it)
+    pub fn fund_prize_vault(ctx: Context<FundPrizeVault>, amount: u64) -> Result<()> {
+        let prize_vault = &ctx.accounts.prize_vault;
+        let funder = &ctx.accounts.funder;
+
+        require!(amount > 0, RaffleError::InvalidPrizeAmount);
+
+        invoke(
+            &system_instruction::transfer(
+                funder.key,
+                prize_vault.to_account_info().key,
+                amount,
+            ),
+            &[
+                funder.to_account_info(),
+                prize_vault.to_account_info(),
+            ],
+        )?;
+
+        msg!("Prize vault funded with {} lamports by {}", amount, funder.key());
+        Ok(())
+    }
+
+    // Pay the weekly prize vault to the top player of the last finished week (callable by anyone).
+    // Whatever a week doesn't pay out rolls over into the next week's prize.
+    pub fn distribute_weekly_prizes(ctx: Context<DistributeWeeklyPrizes>) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard;
+        let rankings = &mut ctx.accounts.rankings;
+        let prize_vault = &mut ctx.accounts.prize_vault;
+        let weekly_winner = &ctx.accounts.weekly_winner;
+        let clock = Clock::get()?;
+
+        // Freeze the week that just ended if no submission has done so yet
+        rankings.roll(clock.unix_timestamp);
+
+        let week = rankings.previous_week;
+        let winner = rankings.previous_weekly_winner.clone();
+        require!(winner.user != Pubkey::default(), RaffleError::NoWinnerDrawn);
+        require!(week > leaderboard.last_weekly_distribution, RaffleError::AlreadyDistributed);
+        require!(weekly_winner.key() == winner.user, RaffleError::InvalidPrizeRecipient);
+
+        let prize = prize_vault_balance(&prize_vault.to_account_info())?;
+        require!(prize > 0, RaffleError::InvalidPrizeAmount);
+        pay_from_prize_vault(prize_vault, &weekly_winner.to_account_info(), prize)?;
+
+        leaderboard.last_weekly_distribution = week;
+        leaderboard.weekly_prize_amount = prize;
+        leaderboard.weekly_winner = winner.user;
+        leaderboard.weekly_winner_tickets = winner.tickets;
+
+        msg!("Week {} prize of {} lamports paid to {} ({} tickets)", week, prize, winner.user, winner.tickets);
+        Ok(())
+    }
+
+    // Split the monthly prize vault 60/30/10 between the top three players of the last finished
+    // month (callable by anyone). Shares of places nobody reached roll over into the next month.
+    pub fn distribute_monthly_prizes(ctx: Context<DistributeMonthlyPrizes>) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard;
+        let rankings = &mut ctx.accounts.rankings;
+        let prize_vault = &mut ctx.accounts.prize_vault;
+        let clock = Clock::get()?;
+
+        // Freeze the month that just ended if no submission has done so yet
+        rankings.roll(clock.unix_timestamp);
+
+        let month = rankings.previous_month;
+        let winners = rankings.previous_monthly_winners.clone();
+        require!(winners[0].user != Pubkey::default(), RaffleError::NoWinnerDrawn);
+        require!(month > leaderboard.last_monthly_distribution, RaffleError::AlreadyDistributed);
+
+        let prize = prize_vault_balance(&prize_vault.to_account_info())?;
+        require!(prize > 0, RaffleError::InvalidPrizeAmount);
+
+        let places = [
+            Some(&ctx.accounts.first_place),
+            ctx.accounts.second_place.as_ref(),
+            ctx.accounts.third_place.as_ref(),
+        ];
+        for ((winner, share), place) in winners.iter().zip(split_monthly_prize(prize)).zip(places) {
+            if winner.user == Pubkey::default() {
+                continue;
+            }
+            let place = place.ok_or(RaffleError::InvalidPrizeRecipient)?;
+            require!(place.key() == winner.user, RaffleError::InvalidPrizeRecipient);
+
+            pay_from_prize_vault(prize_vault, &place.to_account_info(), share)?;
+            msg!("Month {} prize of {} lamports paid to {} ({} tickets)", month, share, winner.user, winner.tickets);
+        }
+
+        leaderboard.last_monthly_distribution = month;
+        leaderboard.monthly_prize_amount = prize;
+        leaderboard.monthly_first_place = winners[0].user;
+        leaderboard.monthly_second_place = winners[1].user;
+        leaderboard.monthly_third_place = winners[2].user;
+        leaderboard.monthly_first_place_tickets = winners[0].tickets;
+        leaderboard.monthly_second_place_tickets = winners[1].tickets;
+        leaderboard.monthly_third_place_tickets = winners[2].tickets;
+        Ok(())
+    }
+}
+
+// Program counter to track raffle IDs
+#[account]
+pub struct ProgramCounter {
+    pub authority: Pubkey,         // Program admin
     pub raffle_count: u32,         // Number of raffles created
 }
 
@@ -392,11 +1055,284 @@ pub struct Raffle {
     pub end_timestamp: i64,        // When the raffle ends
     pub max_tickets: u32,          // Maximum number of tickets
     pub total_tickets: u32,        // Number of tickets sold
-    pub is_active: bool,           // Whether the raffle is active
+    pub is_active: bool,           // Whether the raffle is open for ticket sales
     pub winner: Option<u32>,       // Winning ticket number
     pub raffle_id: String,         // Unique raffle ID (e.g., "7F-SOL-001")
     pub used_numbers: Vec<u8>,   // Bit-packed bitmap to track used ticket numbers (8 tickets per byte)
     pub unique_entrants: u32,      // Number of unique wallets that have entered the raffle
+    pub randomness_account: Pubkey, // Oracle randomness account requested for the draw
+    pub randomness_round: u64,     // Oracle round observed when randomness was requested
+    pub randomness_request_slot: u64, // Slot in which randomness was requested
+    pub randomness: [u8; 32],      // Fulfilled randomness used for the draw
+    pub winning_index: Option<u32>, // Winner's position among sold tickets (ticket-number order)
+    pub draw_mode: DrawMode,       // Where the draw's randomness comes from
+    pub commitment: [u8; 32],      // sha256 of the creator's secret (commit-reveal only)
+    pub status: RaffleStatus,      // Lifecycle state, see RaffleStatus::can_transition_to
+    pub fee_bps: Option<u16>,      // Platform fee in basis points (None: created before per-raffle fees)
+    pub ledger_entries: u32,       // Number of purchases recorded in the ticket ledger
+    pub min_tickets: u32,          // Tickets that must sell for a draw, otherwise buyers are refunded (0: no minimum)
+    pub max_tickets_per_wallet: u32, // Most tickets a single wallet may hold (0: no cap)
+    pub index: u32,                // Raffle number N in [b"raffle", N] and the ID (0: keypair raffle created before PDAs)
+    pub bump: u8,                  // Bump of the raffle PDA
+    pub refunded_tickets: u32,     // Tickets refunded so far while Refunding
+    pub mint: Pubkey,              // Mint tickets are paid in (default: SOL)
+    pub pot: u64,                  // Tokens credited to the vault by ticket sales (token raffles)
+    pub prize: PrizeKind,          // What the winner receives
+    pub prize_mint: Pubkey,        // Escrowed NFT (NFT raffles)
+    pub prize_amount: u64,         // Escrowed prize: 1 while an NFT is held, or the fixed prize deposited
+    pub sales_share_bps: u16,      // Winner's share of sales after the fee (fixed-prize raffles)
+    pub prize_split_bps: Vec<u16>, // Share of the prize per place (empty: a single winner takes it all)
+    pub winners: Vec<u32>,         // Winning ticket number per place (empty: drawn before multiple winners)
+    pub claimed_places: u16,       // Bit per place whose prize has been claimed
+}
+
+impl Raffle {
+    // Largest serialized size of the fields appended after the original layout
+    pub const APPENDED_SPACE: usize = 32 + 8 + 8 + 32 + 5 + 1 + 32 + 1 + 3 + 4 + 4 + 4 + 4 + 1 + 4 // randomness_account..refunded_tickets
+        + 32 + 8 + 1 + 32 + 8 + 2                                                     // mint..sales_share_bps
+        + (4 + 2 * MAX_WINNERS) + (4 + 4 * MAX_WINNERS) + 2;                           // prize_split_bps..claimed_places
+
+    // Account size of a raffle created with `params`. It reserves every appended field at its
+    // largest, so the winners written by the draw always fit.
+    pub fn space(params: &RaffleParams) -> usize {
+        let bitmap_len = (params.max_tickets as usize).min(MAX_BITMAP_SIZE).div_ceil(BITS_PER_BYTE);
+        8 + 32 + (4 + params.name.len().min(MAX_NAME_LEN)) + (4 + params.description.len().min(MAX_DESCRIPTION_LEN))
+            + 8 + 8 + 8 + 4 + 4 + 1 + 5                               // ticket_price..winner
+            + (4 + MAX_RAFFLE_ID_LEN) + (4 + bitmap_len) + 4          // raffle_id..unique_entrants
+            + Self::APPENDED_SPACE
+    }
+
+    // Account size that fits a raffle's original fields plus every appended field at its largest
+    pub fn upgraded_space(data: &[u8]) -> Result<usize> {
+        let mut rest = data.get(8..).ok_or(ErrorCode::AccountDidNotDeserialize)?;
+        LegacyRaffle::deserialize(&mut rest)?;
+        Ok(data.len().max(data.len() - rest.len() + Self::APPENDED_SPACE))
+    }
+
+    // Lifecycle state, deriving it for raffles created before the status field existed
+    // (their padding, or the space added by upgrade_raffle, decodes the field as Active)
+    pub fn current_status(&self) -> RaffleStatus {
+        if self.status == RaffleStatus::Active && !self.is_active {
+            if self.winner.is_some() {
+                return RaffleStatus::Drawn;
+            }
+            if self.total_tickets == 0 {
+                return RaffleStatus::Cancelled;
+            }
+        }
+        self.status
+    }
+
+    // Share of the prize per place, in basis points
+    pub fn prize_tiers(&self) -> Vec<u16> {
+        if self.prize_split_bps.is_empty() {
+            vec![BPS_DENOMINATOR]
+        } else {
+            self.prize_split_bps.clone()
+        }
+    }
+
+    // Winning ticket number per place; raffles drawn before multiple winners only have `winner`
+    pub fn winning_tickets(&self) -> Vec<u32> {
+        if self.winners.is_empty() {
+            self.winner.into_iter().collect()
+        } else {
+            self.winners.clone()
+        }
+    }
+
+    // Whether every drawn place has been claimed. Raffles drawn before multiple winners track
+    // their single claim through the status and the history record only.
+    pub fn all_prizes_claimed(&self) -> bool {
+        self.claimed_places.count_ones() as usize >= self.winners.len()
+    }
+
+    // Token raffles keep their pot in a token vault owned by the raffle PDA
+    pub fn is_token_raffle(&self) -> bool {
+        self.mint != Pubkey::default()
+    }
+
+    // Whether nothing is owed to anyone any more: the prize was claimed, the raffle was
+    // cancelled without sales, or every sold ticket has been refunded
+    pub fn is_settled(&self) -> bool {
+        match self.current_status() {
+            RaffleStatus::Claimed | RaffleStatus::Cancelled => true,
+            RaffleStatus::Refunding => self.refunded_tickets == self.total_tickets,
+            RaffleStatus::Active | RaffleStatus::Drawn => false,
+        }
+    }
+
+    // The state check an instruction acting on the raffle starts with (closing is checked by
+    // check_closable, which also looks at settlement)
+    pub fn require_status(&self, action: RaffleAction) -> Result<()> {
+        use RaffleStatus::*;
+        let status = self.current_status();
+        match action {
+            RaffleAction::BuyTickets => {
+                require!(status == Active && self.is_active, RaffleError::RaffleNotActive);
+            }
+            RaffleAction::RequestDraw | RaffleAction::Draw => {
+                require!(status == Active, RaffleError::InvalidRaffleStatus);
+            }
+            RaffleAction::ClaimPrize => match status {
+                Drawn => {}
+                Active => return err!(RaffleError::NoWinnerDrawn),
+                Claimed => return err!(RaffleError::PrizeAlreadyClaimed),
+                Cancelled | Refunding => return err!(RaffleError::InvalidRaffleStatus),
+            },
+            RaffleAction::Refund => {
+                require!(status == Refunding, RaffleError::RaffleNotRefunding);
+            }
+            RaffleAction::ReturnPrize => {
+                require!(matches!(status, Cancelled | Refunding), RaffleError::RaffleNotRefunding);
+            }
+        }
+        Ok(())
+    }
+
+    // Move to `next` if the lifecycle allows it; sales close in every state but Active
+    pub fn set_status(&mut self, next: RaffleStatus) -> Result<()> {
+        self.status = self.current_status().transition(next)?;
+        self.is_active = self.is_active && next == RaffleStatus::Active;
+        Ok(())
+    }
+}
+
+// Fields of the original Raffle layout, which sizes the upgrade of raffles created with it
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct LegacyRaffle {
+    authority: Pubkey,
+    name: String,
+    description: String,
+    ticket_price: u64,
+    start_timestamp: i64,
+    end_timestamp: i64,
+    max_tickets: u32,
+    total_tickets: u32,
+    is_active: bool,
+    winner: Option<u32>,
+    raffle_id: String,
+    used_numbers: Vec<u8>,
+    unique_entrants: u32,
+}
+
+// Raffle lifecycle
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RaffleStatus {
+    Active,                        // Selling tickets or waiting for the draw
+    Drawn,                         // Winner drawn, prize not yet claimed
+    Claimed,                       // Prize paid out
+    Cancelled,                     // Cancelled with nothing to pay out
+    Refunding,                     // Ticket holders can reclaim their ticket price
+}
+
+impl RaffleStatus {
+    pub fn can_transition_to(self, next: RaffleStatus) -> bool {
+        use RaffleStatus::*;
+        matches!(
+            (self, next),
+            (Active, Drawn) | (Active, Cancelled) | (Active, Refunding) | (Drawn, Claimed)
+        )
+    }
+
+    pub fn transition(self, next: RaffleStatus) -> Result<RaffleStatus> {
+        if self == RaffleStatus::Claimed && next == RaffleStatus::Claimed {
+            return err!(RaffleError::PrizeAlreadyClaimed);
+        }
+        require!(self.can_transition_to(next), RaffleError::InvalidRaffleStatus);
+        Ok(next)
+    }
+}
+
+// Instructions whose allowed raffle states are checked by Raffle::require_status
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RaffleAction {
+    BuyTickets,                    // buy_ticket, buy_tickets
+    RequestDraw,                   // request_randomness, request_reveal
+    Draw,                          // draw_winner, reveal_winner
+    ClaimPrize,                    // claim_prize
+    Refund,                        // claim_refund, process_refunds
+    ReturnPrize,                   // return_prize
+}
+
+// Source of randomness for a raffle's draw
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    Oracle,                        // Randomness from the configured VRF oracle
+    CommitReveal,                  // Creator's revealed secret mixed with a SlotHashes entry
+}
+
+// What the winner of a raffle receives
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PrizeKind {
+    Pot,                           // The ticket pot less the platform fee
+    Nft,                           // An NFT escrowed by the creator, who keeps the pot less the fee
+    Fixed,                         // A guaranteed amount escrowed by the creator, plus a share of sales
+}
+
+// Platform-wide configuration
+#[account]
+pub struct PlatformConfig {
+    pub admin: Pubkey,             // Admin that can update the config
+    pub treasury: Pubkey,          // Account that receives platform fees
+    pub default_fee_bps: u16,      // Fee in basis points given to new raffles
+    pub max_fee_bps: u16,          // Upper bound for the default fee
+    pub weekly_pool_bps: u16,      // Share of each platform fee paid into the weekly prize vault
+    pub monthly_pool_bps: u16,     // Share of each platform fee paid into the monthly prize vault
+}
+
+// Symbol of a mint in raffle IDs, e.g. "USDC" for 7F-USDC-012
+#[account]
+pub struct MintSymbol {
+    pub mint: Pubkey,              // Mint the symbol belongs to
+    pub symbol: String,            // Upper-case ticker, up to MAX_SYMBOL_LEN characters
+}
+
+// Creation parameters shared by SOL and token raffles
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RaffleParams {
+    pub name: String,              // Name of the raffle
+    pub description: String,       // Description of the raffle
+    pub ticket_price: u64,         // Price per ticket in lamports or base units of the mint
+    pub duration_hours: u64,       // Sale duration, at most 30 days
+    pub max_tickets: u32,          // Maximum number of tickets
+    pub commitment: Option<[u8; 32]>, // sha256 of the creator's secret for a commit-reveal draw
+    pub min_tickets: Option<u32>,  // Tickets that must sell for a draw
+    pub max_tickets_per_wallet: Option<u32>, // Most tickets a single wallet may hold
+    pub fixed_prize: Option<u64>,  // Guaranteed prize the creator deposits at creation
+    pub sales_share_bps: Option<u16>, // Winner's share of sales on top of a fixed prize
+    pub prize_split_bps: Option<Vec<u16>>, // Share of the prize per place, e.g. [5000, 3000, 2000]
+}
+
+// Randomness oracle configuration
+#[account]
+pub struct OracleConfig {
+    pub admin: Pubkey,             // Admin that can change the oracle
+    pub oracle_program: Pubkey,    // Program that owns and fulfils randomness accounts
+}
+
+// Randomness accounts are read through a fixed layout so any oracle program can be plugged in,
+// including the mock oracle in programs/mock-oracle for localnet and tests:
+// [0..8] ORACLE_RANDOMNESS_DISCRIMINATOR, [8..16] fulfilment round (u64 LE), [16..48] randomness
+pub struct OracleRandomness {
+    pub round: u64,                // Incremented by the oracle each time it writes new randomness
+    pub value: [u8; 32],           // Latest randomness value
+}
+
+impl OracleRandomness {
+    pub const LEN: usize = 48;
+
+    pub fn try_read(account: &AccountInfo) -> Result<Self> {
+        let data = account.try_borrow_data()?;
+        require!(data.len() >= Self::LEN, RaffleError::InvalidRandomnessAccount);
+        require!(data[..8] == ORACLE_RANDOMNESS_DISCRIMINATOR, RaffleError::InvalidRandomnessAccount);
+
+        let round = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let mut value = [0u8; 32];
+        value.copy_from_slice(&data[16..48]);
+
+        Ok(Self { round, value })
+    }
 }
 
 // Raffle history account structure
@@ -408,14 +1344,72 @@ pub struct RaffleHistory {
     pub end_timestamp: i64,        // When the raffle ended
     pub total_tickets_sold: u32,   // Number of tickets sold
     pub max_tickets: u32,          // Maximum number of tickets
-    pub final_prize_amount: u64,   // Final prize amount in lamports
+    pub final_prize_amount: u64,   // Final prize amount in the raffle's currency
     pub winner_ticket: u32,        // Winning ticket number
     pub winner_address: Pubkey,    // Winner's wallet address
     pub claim_timestamp: i64,      // When the prize was claimed
-    pub transaction_signature: String, // Transaction signature for the claim
+    // Records written before these fields existed held an empty transaction signature string
+    // here, which decodes as zeroes below
+    pub claim_slot: u64,           // Slot the prize was claimed in
+    pub platform_fee: u64,         // Platform fee taken from the pot in the raffle's currency
+    pub randomness: [u8; 32],      // Randomness the winner was drawn from
+    pub winning_index: Option<u32>, // Winner's position among sold tickets
+    pub raffle: Pubkey,            // Raffle the record belongs to
+    pub prize_mint: Pubkey,        // NFT the winner received (default: the pot was the prize)
+    pub winners: Vec<WinnerRecord>, // Every claimed place, in claim order
+}
+
+impl RaffleHistory {
+    // Borsh size of a record with the longest ID and name and a claim for every place
+    pub const SPACE: usize = 8 + (4 + MAX_RAFFLE_ID_LEN) + (4 + MAX_NAME_LEN)
+        + 8 + 8 + 4 + 4 + 8 + 4 + 32 + 8                      // creation_timestamp..claim_timestamp
+        + 8 + 8 + 32 + 5 + 32 + 32                             // claim_slot..prize_mint
+        + 4 + MAX_WINNERS * WinnerRecord::SIZE;                // winners
+
+    // Add a claimed place; the first claim of the raffle also fills in the raffle's details
+    pub fn record_claim(&mut self, raffle: &Raffle, raffle_key: Pubkey, record: WinnerRecord,
+                        first_claim: bool, platform_fee: u64, now: i64) {
+        if first_claim {
+            self.raffle_id = raffle.raffle_id.clone();
+            // Raffles created before names were capped can have longer names
+            self.raffle_name = truncate_text(&raffle.name, MAX_NAME_LEN);
+            self.creation_timestamp = raffle.start_timestamp;
+            self.end_timestamp = raffle.end_timestamp;
+            self.total_tickets_sold = raffle.total_tickets;
+            self.max_tickets = raffle.max_tickets;
+            self.claim_timestamp = now;
+            self.claim_slot = record.claim_slot;
+            self.platform_fee = platform_fee;
+            self.randomness = raffle.randomness;
+            self.winning_index = raffle.winning_index;
+            self.raffle = raffle_key;
+            self.prize_mint = raffle.prize_mint;
+        }
+        // The single-winner fields describe first place
+        if record.place == 0 {
+            self.winner_ticket = record.ticket;
+            self.winner_address = record.winner;
+        }
+        self.final_prize_amount += record.amount;
+        self.winners.push(record);
+    }
+}
+
+// One winner's claim in a raffle history record
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct WinnerRecord {
+    pub place: u8,                 // Place, 0 being first
+    pub ticket: u32,               // Winning ticket number
+    pub winner: Pubkey,            // Winner's wallet address
+    pub amount: u64,               // Prize paid in the raffle's currency (0 for an NFT)
+    pub claim_slot: u64,           // Slot the prize was claimed in
+}
+
+impl WinnerRecord {
+    pub const SIZE: usize = 1 + 4 + 32 + 8 + 8;
 }
 
-// Ticket account structure
+// Legacy ticket account structure (one per ticket, superseded by the ticket ledger)
 #[account]
 pub struct Ticket {
     pub buyer: Pubkey,                   // Buyer of the ticket
@@ -423,11 +1417,79 @@ pub struct Ticket {
     pub ticket_number: u32,               // Ticket number
 }
 
-// Participant flag account to track unique entrants per raffle
+// Ticket ledger chunk: one buyer's purchases in a raffle, kept sorted by first_number.
+// A raffle's ledger is the union of its buyers' chunks. Chunks are addressed by
+// (raffle, buyer, chunk_index), so concurrent buyers never compete for the same account.
+#[account]
+pub struct TicketLedger {
+    pub raffle: Pubkey,                  // Raffle the ledger belongs to
+    pub buyer: Pubkey,                   // Buyer whose purchases this chunk holds (and who paid its rent)
+    pub chunk_index: u32,                // Position of this chunk among the buyer's chunks
+    pub entries: Vec<LedgerEntry>,       // Purchases, up to LEDGER_CHUNK_CAPACITY
+}
+
+impl TicketLedger {
+    pub const SPACE: usize = 8 + size_of::<TicketLedger>()
+        + LEDGER_CHUNK_CAPACITY as usize * size_of::<LedgerEntry>();
+
+    // Fill in the header of a freshly created chunk
+    pub fn set_up(&mut self, raffle: Pubkey, buyer: Pubkey, chunk_index: u32) {
+        if self.raffle == Pubkey::default() {
+            self.raffle = raffle;
+            self.buyer = buyer;
+            self.chunk_index = chunk_index;
+        }
+    }
+
+    // Add an entry, keeping the chunk sorted by first_number
+    pub fn insert(&mut self, entry: LedgerEntry) -> Result<()> {
+        require!(self.entries.len() < LEDGER_CHUNK_CAPACITY as usize, RaffleError::LedgerChunkFull);
+
+        let position = self.entries.partition_point(|existing| existing.first_number < entry.first_number);
+        self.entries.insert(position, entry);
+        Ok(())
+    }
+
+    // Binary search for the entry whose range contains `ticket_number`
+    pub fn find_entry(&self, ticket_number: u32) -> Option<&LedgerEntry> {
+        self.entries
+            .binary_search_by(|entry| {
+                if entry.first_number > ticket_number {
+                    std::cmp::Ordering::Greater
+                } else if entry.first_number + entry.count <= ticket_number {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+}
+
+// One purchase of consecutive tickets
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LedgerEntry {
+    pub buyer: Pubkey,                   // Buyer of the tickets
+    pub first_number: u32,               // First ticket number bought
+    pub count: u32,                      // Number of consecutive tickets
+    pub refunded: bool,                  // Whether the entry has been refunded
+    pub amount_paid: u64,                // Amount that reached the pot, net of any transfer fee
+}
+
+// Participant flag account to track unique entrants per raffle and each entrant's holdings
 #[account]
 pub struct ParticipantFlag {
     pub raffle: Pubkey,     // The raffle this participation is for
     pub participant: Pubkey, // The participant's wallet address
+    // Fields below were added after launch; older flags are grown by upgrade_participant_flag
+    // and count purchases from the upgrade onwards
+    pub ticket_count: u32,  // Tickets bought in this raffle
+    pub amount_paid: u64,   // Amount that reached the pot for those tickets, in lamports or raffle mint units
+}
+
+impl ParticipantFlag {
+    pub const SPACE: usize = 8 + size_of::<ParticipantFlag>();
 }
 
 // User stats account structure for leaderboard tracking
@@ -437,8 +1499,21 @@ pub struct UserStats {
     pub total_tickets_purchased: u32,     // Total tickets purchased all-time
     pub weekly_tickets: u32,              // Tickets purchased in current week
     pub monthly_tickets: u32,             // Tickets purchased in current month
-    pub current_week: u32,                // Current week number
-    pub current_month: u32,               // Current month number
+    pub current_week: u32,                // Current ISO week as yyyyww (legacy: weeks since epoch)
+    pub current_month: u32,               // Current month as yyyymm (legacy: 30-day months since epoch)
+    // Fields below were added in version 1; older accounts are grown by upgrade_user_stats
+    // and count from the upgrade onwards
+    pub version: u8,                      // Layout version, see USER_STATS_VERSION
+    pub total_spent: u64,                 // Lamports spent on tickets
+    pub raffles_entered: u32,             // Number of raffles entered
+    pub raffles_won: u32,                 // Number of raffles the user claimed a prize in
+    pub total_won: u64,                   // Lamports won across all prizes
+    pub biggest_win: u64,                 // Largest single prize in lamports
+    pub last_activity: i64,               // Timestamp of the last purchase or claim
+}
+
+impl UserStats {
+    pub const SPACE: usize = 8 + size_of::<UserStats>();
 }
 
 // Leaderboard account structure for tracking top players and prize distribution
@@ -448,13 +1523,13 @@ pub struct Leaderboard {
     
     // Weekly tracking
     pub last_weekly_distribution: u32,    // Last week number when prizes were distributed
-    pub weekly_prize_amount: u64,         // Weekly prize amount in lamports
+    pub weekly_prize_amount: u64,         // Weekly prize paid at the last distribution in lamports
     pub weekly_winner: Pubkey,            // Weekly winner address
     pub weekly_winner_tickets: u32,       // Number of tickets purchased by weekly winner
     
     // Monthly tracking
     pub last_monthly_distribution: u32,   // Last month number when prizes were distributed
-    pub monthly_prize_amount: u64,        // Monthly prize pool amount in lamports
+    pub monthly_prize_amount: u64,        // Monthly prize pool split at the last distribution in lamports
     pub monthly_first_place: Pubkey,      // First place winner address
     pub monthly_second_place: Pubkey,     // Second place winner address
     pub monthly_third_place: Pubkey,      // Third place winner address
@@ -463,6 +1538,72 @@ pub struct Leaderboard {
     pub monthly_third_place_tickets: u32, // Number of tickets purchased by third place
 }
 
+// On-chain top-N tables for the current week and month. Purchases only update the buyer's
+// UserStats; a player enters or moves up a table when submit_rankings copies their counts in.
+// When a period ends its top players are frozen as the winners to be paid.
+#[account]
+pub struct Rankings {
+    pub week: u32,                        // Week the weekly table is for
+    pub weekly: Vec<RankEntry>,           // Top WEEKLY_TOP_PLAYERS by weekly tickets, best first
+    pub month: u32,                       // Month the monthly table is for
+    pub monthly: Vec<RankEntry>,          // Top MONTHLY_TOP_PLAYERS by monthly tickets, best first
+    pub previous_week: u32,               // Last finished week with purchases
+    pub previous_weekly_winner: RankEntry, // Top player of previous_week
+    pub previous_month: u32,              // Last finished month with purchases
+    pub previous_monthly_winners: [RankEntry; 3], // Top three players of previous_month
+}
+
+impl Rankings {
+    pub const SPACE: usize = 8 + size_of::<Rankings>()
+        + (WEEKLY_TOP_PLAYERS + MONTHLY_TOP_PLAYERS) * size_of::<RankEntry>();
+
+    // Freeze the winners of a finished week or month and start empty tables for the new one.
+    // A finished period replaces the frozen one even if that was never distributed.
+    pub fn roll(&mut self, now: i64) {
+        let week = get_week_number(now);
+        if self.week != week {
+            if let Some(top) = self.weekly.first() {
+                self.previous_week = self.week;
+                self.previous_weekly_winner = top.clone();
+            }
+            self.week = week;
+            self.weekly.clear();
+        }
+
+        let month = get_month_number(now);
+        if self.month != month {
+            if !self.monthly.is_empty() {
+                self.previous_month = self.month;
+                self.previous_monthly_winners = std::array::from_fn(|place| {
+                    self.monthly.get(place).cloned().unwrap_or_default()
+                });
+            }
+            self.month = month;
+            self.monthly.clear();
+        }
+    }
+
+    // Update the user's position with their current weekly and monthly ticket counts
+    pub fn record(&mut self, user: Pubkey, weekly_tickets: u32, monthly_tickets: u32, now: i64) {
+        self.roll(now);
+        update_ranking(&mut self.weekly, WEEKLY_TOP_PLAYERS, user, weekly_tickets);
+        update_ranking(&mut self.monthly, MONTHLY_TOP_PLAYERS, user, monthly_tickets);
+    }
+}
+
+// A player's ticket count for a ranking period
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct RankEntry {
+    pub user: Pubkey,                     // Player wallet address
+    pub tickets: u32,                     // Tickets bought in the period
+}
+
+// Program-owned vault holding a leaderboard prize pool (one for weekly, one for monthly prizes)
+#[account]
+pub struct PrizeVault {
+    pub total_paid: u64,                  // Lamports paid out to leaderboard winners so far
+}
+
 // Context for initializing the program counter
 #[derive(Accounts)]
 pub struct InitializeProgramCounter<'info> {
@@ -476,104 +1617,386 @@ pub struct InitializeProgramCounter<'info> {
     pub system_program: Program<'info, System>,
 }
 
-// Context for initializing a raffle
+// Context for initializing the platform config
 #[derive(Accounts)]
-pub struct InitializeRaffle<'info> {
-    #[account(init, payer = authority, space = 8 + size_of::<Raffle>() + 500)]
-    pub raffle: Account<'info, Raffle>,
-    
-    #[account(mut, seeds = [b"program-counter"], bump)]
+pub struct InitializePlatformConfig<'info> {
+    #[account(init, payer = authority, space = 8 + size_of::<PlatformConfig>(),
+             seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    #[account(seeds = [b"program-counter"], bump)]
     pub program_counter: Account<'info, ProgramCounter>,
-    
+
     #[account(mut)]
     pub authority: Signer<'info>,
-    
+
     pub system_program: Program<'info, System>,
 }
 
-// Context for buying a ticket
+// Context for updating the platform config
 #[derive(Accounts)]
-#[instruction(bump: u8)]
-pub struct BuyTicket<'info> {
-    #[account(mut)]
-    pub raffle: Account<'info, Raffle>,
-    
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    #[account(init, payer = buyer, space = 8 + size_of::<Ticket>(), seeds = [b"ticket", raffle.key().as_ref(), &raffle.total_tickets.to_le_bytes()], bump)]
-    pub ticket: Account<'info, Ticket>,
-    
-    #[account(mut, seeds = [b"user-stats", buyer.key().as_ref()], bump)]
-    pub user_stats: Account<'info, UserStats>,
-    
-    // This account is created to track unique entrants
-    // Using a separate flag account to track first-time participants
-    #[account(init_if_needed, payer = buyer, space = 8 + size_of::<ParticipantFlag>(),
-              seeds = [b"participant", raffle.key().as_ref(), buyer.key().as_ref()],
-              bump)]
-    pub participant_flag: Account<'info, ParticipantFlag>,
-    
-    pub system_program: Program<'info, System>,
+pub struct UpdatePlatformConfig<'info> {
+    #[account(mut, seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    pub admin: Signer<'info>,
 }
 
-// Context for initializing user stats
+// Context for initializing the oracle config
 #[derive(Accounts)]
-pub struct InitializeUserStats<'info> {
-    #[account(init, payer = user, space = 8 + size_of::<UserStats>(), seeds = [b"user-stats", user.key().as_ref()], bump)]
-    pub user_stats: Account<'info, UserStats>,
-    
+pub struct InitializeOracleConfig<'info> {
+    #[account(init, payer = authority, space = 8 + size_of::<OracleConfig>(),
+             seeds = [b"oracle-config"], bump)]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    #[account(seeds = [b"program-counter"], bump)]
+    pub program_counter: Account<'info, ProgramCounter>,
+
     #[account(mut)]
-    pub user: Signer<'info>,
-    
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for changing the oracle program
+#[derive(Accounts)]
+pub struct SetOracleProgram<'info> {
+    #[account(mut, seeds = [b"oracle-config"], bump)]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    pub admin: Signer<'info>,
+}
+
+// Context for initializing a raffle
+#[derive(Accounts)]
+#[instruction(params: RaffleParams)]
+pub struct InitializeRaffle<'info> {
+    #[account(mut, seeds = [b"program-counter"], bump)]
+    pub program_counter: Account<'info, ProgramCounter>,
+    
+    // Raffle N lives at the PDA of [b"raffle", N], so clients can enumerate raffles 1..=raffle_count
+    #[account(init, payer = authority, space = Raffle::space(&params),
+              seeds = [b"raffle".as_ref(), &(program_counter.raffle_count + 1).to_le_bytes()], bump)]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    // NFT raffles only: the prize NFT, the creator's account holding it and the escrow vault
+    // owned by the raffle PDA
+    pub prize_mint: Option<InterfaceAccount<'info, Mint>>,
+    
+    #[account(mut, token::mint = prize_mint, token::authority = authority)]
+    pub creator_prize_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(init, payer = authority, seeds = [b"prize-vault", raffle.key().as_ref()], bump,
+              token::mint = prize_mint, token::authority = raffle, token::token_program = token_program)]
+    pub prize_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for initializing a token raffle
+#[derive(Accounts)]
+#[instruction(params: RaffleParams)]
+pub struct InitializeTokenRaffle<'info> {
+    #[account(mut, seeds = [b"program-counter"], bump)]
+    pub program_counter: Account<'info, ProgramCounter>,
+    
+    #[account(init, payer = authority, space = Raffle::space(&params),
+              seeds = [b"raffle".as_ref(), &(program_counter.raffle_count + 1).to_le_bytes()], bump)]
+    pub raffle: Account<'info, Raffle>,
+    
+    #[account(seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    // Only mints with a registered symbol can be used
+    #[account(seeds = [b"mint-symbol", mint.key().as_ref()], bump)]
+    pub mint_symbol: Account<'info, MintSymbol>,
+    
+    // Holds the pot; owned by the raffle PDA so only the program can pay out of it
+    #[account(init, payer = authority, seeds = [b"raffle-vault", raffle.key().as_ref()], bump,
+              token::mint = mint, token::authority = raffle, token::token_program = token_program)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    // Fixed-prize raffles only: the creator's token account funding the prize
+    #[account(mut)]
+    pub creator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for registering a mint symbol
+#[derive(Accounts)]
+pub struct RegisterMintSymbol<'info> {
+    #[account(init_if_needed, payer = admin, space = 8 + size_of::<MintSymbol>() + MAX_SYMBOL_LEN,
+              seeds = [b"mint-symbol", mint.key().as_ref()], bump)]
+    pub mint_symbol: Account<'info, MintSymbol>,
+    
+    pub mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for buying one or more tickets
+#[derive(Accounts)]
+#[instruction(chunk_index: u32)]
+pub struct BuyTicket<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    // The buyer's ledger chunk that receives the purchase
+    #[account(init_if_needed, payer = buyer, space = TicketLedger::SPACE,
+              seeds = [b"ticket-ledger", raffle.key().as_ref(), buyer.key().as_ref(), &chunk_index.to_le_bytes()],
+              bump)]
+    pub ticket_ledger: Account<'info, TicketLedger>,
+    
+    // Created on the buyer's first purchase; an older layout must go through upgrade_user_stats first
+    #[account(init_if_needed, payer = buyer, space = UserStats::SPACE,
+              seeds = [b"user-stats", buyer.key().as_ref()], bump)]
+    pub user_stats: Account<'info, UserStats>,
+    
+    // This account is created to track unique entrants
+    // Using a separate flag account to track first-time participants and their holdings.
+    // An older layout must go through upgrade_participant_flag first.
+    #[account(init_if_needed, payer = buyer, space = ParticipantFlag::SPACE,
+              seeds = [b"participant", raffle.key().as_ref(), buyer.key().as_ref()],
+              bump)]
+    pub participant_flag: Account<'info, ParticipantFlag>,
+    
+    // Token raffles only: the buyer's token account paying for the tickets
+    #[account(mut)]
+    pub buyer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    // Token raffles only: the raffle vault receiving the payment
+    #[account(mut, seeds = [b"raffle-vault", raffle.key().as_ref()], bump)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(address = raffle.mint)]
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for migrating a legacy ticket into the ledger
+#[derive(Accounts)]
+#[instruction(chunk_index: u32)]
+pub struct MigrateLegacyTicket<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(mut, close = buyer, has_one = buyer, constraint = ticket.raffle == raffle.key())]
+    pub ticket: Account<'info, Ticket>,
+
+    #[account(init_if_needed, payer = buyer, space = TicketLedger::SPACE,
+              seeds = [b"ticket-ledger", raffle.key().as_ref(), buyer.key().as_ref(), &chunk_index.to_le_bytes()],
+              bump)]
+    pub ticket_ledger: Account<'info, TicketLedger>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Context for initializing user stats
+#[derive(Accounts)]
+pub struct InitializeUserStats<'info> {
+    #[account(init, payer = user, space = UserStats::SPACE, seeds = [b"user-stats", user.key().as_ref()], bump)]
+    pub user_stats: Account<'info, UserStats>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for upgrading a raffle to the current layout
+#[derive(Accounts)]
+pub struct UpgradeRaffle<'info> {
+    /// CHECK: May still have an older, shorter layout, so it is checked and grown in the handler
+    #[account(mut, owner = crate::ID)]
+    pub raffle: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for marking a legacy raffle as claimed
+#[derive(Accounts)]
+pub struct MarkLegacyClaimed<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+    
+    // Written by the original claim_prize at a keypair address; matched to the raffle in the handler
+    pub legacy_history: Account<'info, RaffleHistory>,
+}
+
+// Context for upgrading user stats to the current layout
+#[derive(Accounts)]
+pub struct UpgradeUserStats<'info> {
+    /// CHECK: May still have an older, shorter layout, so it is checked and grown in the handler
+    #[account(mut, owner = crate::ID, seeds = [b"user-stats", user.key().as_ref()], bump)]
+    pub user_stats: UncheckedAccount<'info>,
+    
+    /// CHECK: Only used to derive the stats address
+    pub user: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+// Context for upgrading a participant flag to the current layout
+#[derive(Accounts)]
+pub struct UpgradeParticipantFlag<'info> {
+    /// CHECK: May still have the original, shorter layout, so it is checked and grown in the handler
+    #[account(mut, owner = crate::ID,
+              seeds = [b"participant", raffle.key().as_ref(), participant.key().as_ref()], bump)]
+    pub participant_flag: UncheckedAccount<'info>,
+    
+    /// CHECK: Only used to derive the flag address
+    pub raffle: UncheckedAccount<'info>,
+    
+    /// CHECK: Only used to derive the flag address
+    pub participant: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
     pub system_program: Program<'info, System>,
 }
 
 // Context for initializing leaderboard
 #[derive(Accounts)]
 pub struct InitializeLeaderboard<'info> {
-    #[account(init, payer = admin, space = 8 + size_of::<Leaderboard>() + 200)]
+    #[account(init, payer = admin, space = 8 + size_of::<Leaderboard>() + 200,
+              seeds = [b"leaderboard"], bump)]
     pub leaderboard: Account<'info, Leaderboard>,
     
+    #[account(init, payer = admin, space = Rankings::SPACE, seeds = [b"rankings"], bump)]
+    pub rankings: Account<'info, Rankings>,
+    
+    #[account(init, payer = admin, space = 8 + size_of::<PrizeVault>(),
+              seeds = [b"weekly-prize-vault"], bump)]
+    pub weekly_prize_vault: Account<'info, PrizeVault>,
+    
+    #[account(init, payer = admin, space = 8 + size_of::<PrizeVault>(),
+              seeds = [b"monthly-prize-vault"], bump)]
+    pub monthly_prize_vault: Account<'info, PrizeVault>,
+    
+    #[account(seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
     #[account(mut)]
     pub admin: Signer<'info>,
     
     pub system_program: Program<'info, System>,
 }
 
-// Context for distributing weekly prizes
+// Context for funding a prize vault
 #[derive(Accounts)]
-pub struct DistributeWeeklyPrizes<'info> {
+pub struct FundPrizeVault<'info> {
     #[account(mut)]
-    pub leaderboard: Account<'info, Leaderboard>,
+    pub prize_vault: Account<'info, PrizeVault>,
     
-    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
 }
 
-// Context for setting weekly winner
+// Context for submitting a player's ticket counts to the rankings
 #[derive(Accounts)]
-pub struct SetLeaderboardWinner<'info> {
-    #[account(mut)]
+pub struct SubmitRankings<'info> {
+    #[account(mut, seeds = [b"rankings"], bump)]
+    pub rankings: Account<'info, Rankings>,
+    
+    #[account(seeds = [b"user-stats", user_stats.user.as_ref()], bump)]
+    pub user_stats: Account<'info, UserStats>,
+}
+
+// Context for distributing weekly prizes
+#[derive(Accounts)]
+pub struct DistributeWeeklyPrizes<'info> {
+    #[account(mut, seeds = [b"leaderboard"], bump)]
     pub leaderboard: Account<'info, Leaderboard>,
     
-    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"rankings"], bump)]
+    pub rankings: Account<'info, Rankings>,
+    
+    #[account(mut, seeds = [b"weekly-prize-vault"], bump)]
+    pub prize_vault: Account<'info, PrizeVault>,
+    
+    /// CHECK: Receives the prize; checked against the frozen weekly winner in the handler
+    #[account(mut)]
+    pub weekly_winner: UncheckedAccount<'info>,
 }
 
 // Context for distributing monthly prizes
 #[derive(Accounts)]
 pub struct DistributeMonthlyPrizes<'info> {
-    #[account(mut)]
+    #[account(mut, seeds = [b"leaderboard"], bump)]
     pub leaderboard: Account<'info, Leaderboard>,
     
-    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"rankings"], bump)]
+    pub rankings: Account<'info, Rankings>,
+    
+    #[account(mut, seeds = [b"monthly-prize-vault"], bump)]
+    pub prize_vault: Account<'info, PrizeVault>,
+    
+    /// CHECK: Receives the first place share; checked against the frozen winners in the handler
+    #[account(mut)]
+    pub first_place: UncheckedAccount<'info>,
+    
+    /// CHECK: Receives the second place share (omitted if nobody placed second)
+    #[account(mut)]
+    pub second_place: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: Receives the third place share (omitted if nobody placed third)
+    #[account(mut)]
+    pub third_place: Option<UncheckedAccount<'info>>,
 }
 
-// Context for setting monthly winners
+// Context for requesting draw randomness
 #[derive(Accounts)]
-pub struct SetMonthlyWinners<'info> {
+pub struct RequestRandomness<'info> {
     #[account(mut)]
-    pub leaderboard: Account<'info, Leaderboard>,
-    
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(seeds = [b"oracle-config"], bump)]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    /// CHECK: Must be owned by the configured oracle program; parsed via OracleRandomness
+    #[account(owner = oracle_config.oracle_program @ RaffleError::InvalidRandomnessAccount)]
+    pub randomness_account: UncheckedAccount<'info>,
+
     pub authority: Signer<'info>,
 }
 
@@ -582,12 +2005,76 @@ pub struct SetMonthlyWinners<'info> {
 pub struct DrawWinner<'info> {
     #[account(mut)]
     pub raffle: Account<'info, Raffle>,
-    
+
+    #[account(seeds = [b"oracle-config"], bump)]
+    pub oracle_config: Account<'info, OracleConfig>,
+
+    /// CHECK: Must be the account recorded at request time and still owned by the oracle program
+    #[account(address = raffle.randomness_account @ RaffleError::InvalidRandomnessAccount,
+              owner = oracle_config.oracle_program @ RaffleError::InvalidRandomnessAccount)]
+    pub randomness_account: UncheckedAccount<'info>,
+}
+
+// Context for locking the reveal slot
+#[derive(Accounts)]
+pub struct RequestReveal<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+
+    pub authority: Signer<'info>,
+}
+
+// Context for revealing the commit-reveal secret
+#[derive(Accounts)]
+pub struct RevealWinner<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+
+    /// CHECK: SlotHashes sysvar, read directly since it is too large to deserialize
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+
     pub authority: Signer<'info>,
 }
 
+// Context for opening refunds
+#[derive(Accounts)]
+pub struct OpenRefunds<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+}
+
+// Context for claiming a refund
+#[derive(Accounts)]
+#[instruction(chunk_index: u32)]
+pub struct ClaimRefund<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+
+    #[account(mut, seeds = [b"ticket-ledger", raffle.key().as_ref(), buyer.key().as_ref(), &chunk_index.to_le_bytes()],
+              bump)]
+    pub ticket_ledger: Account<'info, TicketLedger>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    // Token raffles only: the buyer's token account receiving the refund
+    #[account(mut)]
+    pub buyer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // Token raffles only: the raffle vault paying the refund
+    #[account(mut, seeds = [b"raffle-vault", raffle.key().as_ref()], bump)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(address = raffle.mint)]
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
 // Context for claiming a prize
 #[derive(Accounts)]
+#[instruction(chunk_index: u32)]
 pub struct ClaimPrize<'info> {
     #[account(mut)]
     pub raffle: Account<'info, Raffle>,
@@ -595,50 +2082,215 @@ pub struct ClaimPrize<'info> {
     #[account(mut)]
     pub winner: Signer<'info>,
     
-    #[account(constraint = winning_ticket.raffle == raffle.key())]
-    pub winning_ticket: Account<'info, Ticket>,
+    // The winner's ledger chunk holding the winning ticket number
+    #[account(seeds = [b"ticket-ledger", raffle.key().as_ref(), winner.key().as_ref(), &chunk_index.to_le_bytes()],
+              bump)]
+    pub ticket_ledger: Account<'info, TicketLedger>,
+    
+    #[account(seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+
+    /// CHECK: Receives the platform fee; must be the treasury in the platform config
+    #[account(mut, address = platform_config.treasury @ RaffleError::InvalidFeeRecipient)]
+    pub fee_treasury: UncheckedAccount<'info>,
+    
+    #[account(mut, seeds = [b"weekly-prize-vault"], bump)]
+    pub weekly_prize_vault: Account<'info, PrizeVault>,
+    
+    #[account(mut, seeds = [b"monthly-prize-vault"], bump)]
+    pub monthly_prize_vault: Account<'info, PrizeVault>,
+    
+    /// CHECK: May still have an older, shorter layout, so it is checked and grown in the handler
+    #[account(mut, owner = crate::ID, seeds = [b"user-stats", winner.key().as_ref()], bump)]
+    pub winner_stats: UncheckedAccount<'info>,
+    
+    // Token raffles only: the winner's and the treasury's token accounts and the raffle vault.
+    // The treasury's account is only needed by the claim that pays the fee.
+    #[account(mut)]
+    pub winner_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(mut, seeds = [b"raffle-vault", raffle.key().as_ref()], bump)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(address = raffle.mint)]
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    
+    // NFT raffles only: the escrowed NFT, the winner's account receiving it and the creator
+    // receiving the proceeds and the vault's rent
+    #[account(mut, seeds = [b"prize-vault", raffle.key().as_ref()], bump)]
+    pub prize_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(address = raffle.prize_mint)]
+    pub prize_mint: Option<InterfaceAccount<'info, Mint>>,
+    
+    #[account(mut)]
+    pub winner_prize_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: The raffle's creator; NFT and fixed-prize raffles pay it the ticket proceeds
+    #[account(mut, address = raffle.authority)]
+    pub creator: Option<UncheckedAccount<'info>>,
     
-    /// CHECK: This is the authority that receives the platform fee
     #[account(mut)]
-    pub authority: AccountInfo<'info>,
+    pub creator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
     
-    #[account(init, payer = winner, space = 8 + size_of::<RaffleHistory>() + 300)]
+    // One history record per raffle, at the PDA of [b"raffle-history", raffle]
+    #[account(init_if_needed, payer = winner,
+              space = RaffleHistory::SPACE,
+              seeds = [b"raffle-history", raffle.key().as_ref()], bump)]
     pub raffle_history: Account<'info, RaffleHistory>,
     
     pub system_program: Program<'info, System>,
 }
 
-// Context for canceling a raffle
+// Context for returning an escrowed prize to the creator
 #[derive(Accounts)]
-pub struct CancelRaffle<'info> {
+pub struct ReturnPrize<'info> {
     #[account(mut)]
     pub raffle: Account<'info, Raffle>,
     
-    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"prize-vault", raffle.key().as_ref()], bump)]
+    pub prize_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(address = raffle.prize_mint)]
+    pub prize_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut, token::authority = creator)]
+    pub creator_prize_account: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: The raffle's creator; receives the vault's rent
+    #[account(mut, address = raffle.authority)]
+    pub creator: UncheckedAccount<'info>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
-// Error enum for the raffle program
-#[error_code]
-pub enum RaffleError {
-    #[msg("The raffle is not active")]
-    RaffleNotActive,
+// Context for pushing refunds to buyers; ledger chunks and buyer wallets are passed
+// as remaining accounts
+#[derive(Accounts)]
+pub struct ProcessRefunds<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
     
-    #[msg("The raffle has already ended")]
-    RaffleEnded,
+    // Token raffles only: the raffle vault paying the refunds
+    #[account(mut, seeds = [b"raffle-vault", raffle.key().as_ref()], bump)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
     
-    #[msg("The raffle is full")]
-    RaffleFull,
+    #[account(address = raffle.mint)]
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
     
-    #[msg("Unauthorized access")]
-    UnauthorizedAccess,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+// Context for closing a raffle
+#[derive(Accounts)]
+pub struct CloseRaffle<'info> {
+    #[account(mut, close = creator)]
+    pub raffle: Account<'info, Raffle>,
     
-    #[msg("The raffle has not ended yet")]
-    RaffleNotEnded,
+    /// CHECK: Receives the rent and leftover lamports; must be the raffle creator
+    #[account(mut, address = raffle.authority @ RaffleError::UnauthorizedAccess)]
+    pub creator: UncheckedAccount<'info>,
     
-    #[msg("No tickets have been sold")]
-    NoTicketsSold,
+    // Required for raffles with a winner; matched to the raffle by its ID in the handler
+    pub raffle_history: Option<Account<'info, RaffleHistory>>,
     
-    #[msg("No winner has been drawn")]
+    // Token raffles only: the vault to close and the creator's token account for its dust
+    #[account(mut, seeds = [b"raffle-vault", raffle.key().as_ref()], bump)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(mut, address = raffle.mint)]
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    
+    #[account(mut)]
+    pub creator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+// Context for closing a participant flag
+#[derive(Accounts)]
+pub struct CloseParticipantFlag<'info> {
+    #[account(mut, close = participant, has_one = participant)]
+    pub participant_flag: Account<'info, ParticipantFlag>,
+    
+    /// CHECK: Checked to be settled in the handler; may already be closed
+    #[account(address = participant_flag.raffle)]
+    pub raffle: UncheckedAccount<'info>,
+    
+    /// CHECK: Receives the rent; must be the participant who paid it
+    #[account(mut)]
+    pub participant: UncheckedAccount<'info>,
+}
+
+// Context for closing a ticket ledger chunk
+#[derive(Accounts)]
+pub struct CloseTicketLedger<'info> {
+    #[account(mut, close = buyer, has_one = buyer)]
+    pub ticket_ledger: Account<'info, TicketLedger>,
+    
+    /// CHECK: Checked to be settled in the handler; may already be closed
+    #[account(address = ticket_ledger.raffle)]
+    pub raffle: UncheckedAccount<'info>,
+    
+    /// CHECK: Receives the rent; must be the buyer who paid it
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+}
+
+// Context for closing a legacy ticket
+#[derive(Accounts)]
+pub struct CloseLegacyTicket<'info> {
+    #[account(mut, close = buyer, has_one = buyer)]
+    pub ticket: Account<'info, Ticket>,
+    
+    /// CHECK: Checked to be settled in the handler; may already be closed
+    #[account(address = ticket.raffle)]
+    pub raffle: UncheckedAccount<'info>,
+    
+    /// CHECK: Receives the rent; must be the buyer who paid it
+    #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
+}
+
+// Context for canceling a raffle
+#[derive(Accounts)]
+pub struct CancelRaffle<'info> {
+    #[account(mut)]
+    pub raffle: Account<'info, Raffle>,
+    
+    #[account(seeds = [b"platform-config"], bump)]
+    pub platform_config: Account<'info, PlatformConfig>,
+    
+    pub authority: Signer<'info>,
+}
+
+// Error enum for the raffle program
+#[error_code]
+pub enum RaffleError {
+    #[msg("The raffle is not active")]
+    RaffleNotActive,
+    
+    #[msg("The raffle has already ended")]
+    RaffleEnded,
+    
+    #[msg("The raffle is full")]
+    RaffleFull,
+    
+    #[msg("Unauthorized access")]
+    UnauthorizedAccess,
+    
+    #[msg("The raffle has not ended yet")]
+    RaffleNotEnded,
+    
+    #[msg("No tickets have been sold")]
+    NoTicketsSold,
+    
+    #[msg("No winner has been drawn")]
     NoWinnerDrawn,
     
     #[msg("This is not the winning ticket")]
@@ -676,20 +2328,1986 @@ pub enum RaffleError {
     
     #[msg("Invalid prize amount")]
     InvalidPrizeAmount,
+
+    #[msg("A winner has already been drawn")]
+    WinnerAlreadyDrawn,
+
+    #[msg("Randomness has already been requested for this raffle")]
+    RandomnessAlreadyRequested,
+
+    #[msg("Randomness has not been requested for this raffle")]
+    RandomnessNotRequested,
+
+    #[msg("The oracle has not fulfilled the randomness request yet")]
+    RandomnessNotFulfilled,
+
+    #[msg("Invalid randomness account")]
+    InvalidRandomnessAccount,
+
+    #[msg("This instruction does not match the raffle's draw mode")]
+    WrongDrawMode,
+
+    #[msg("The revealed secret does not match the commitment")]
+    InvalidCommitment,
+
+    #[msg("The reveal window has closed")]
+    RevealWindowClosed,
+
+    #[msg("The reveal window is still open")]
+    RevealWindowOpen,
+
+    #[msg("The slot hash needed for the draw is no longer available")]
+    SlotHashUnavailable,
+
+    #[msg("The raffle is not refunding")]
+    RaffleNotRefunding,
+
+    #[msg("Invalid ticket ledger index")]
+    InvalidLedgerIndex,
+
+    #[msg("These tickets have already been refunded")]
+    AlreadyRefunded,
+
+    #[msg("This ticket ledger chunk is full, use the next chunk index")]
+    LedgerChunkFull,
+
+    #[msg("Not enough consecutive ticket numbers are available")]
+    NoContiguousTickets,
+
+    #[msg("The raffle is not in a state that allows this action")]
+    InvalidRaffleStatus,
+
+    #[msg("The prize has already been claimed")]
+    PrizeAlreadyClaimed,
+
+    #[msg("Invalid platform fee")]
+    InvalidFee,
+
+    #[msg("The fee recipient is not the configured treasury")]
+    InvalidFeeRecipient,
+
+    #[msg("Fewer tickets were sold than the raffle's minimum")]
+    BelowMinimumTickets,
+
+    #[msg("Refund accounts must be (ticket ledger, buyer) pairs for this raffle")]
+    InvalidRefundAccounts,
+
+    #[msg("The prize recipient is not the ranked winner")]
+    InvalidPrizeRecipient,
+
+    #[msg("This wallet has reached the raffle's ticket limit")]
+    WalletTicketLimitReached,
+
+    #[msg("The raffle still owes a prize or refunds")]
+    RaffleNotSettled,
+
+    #[msg("The raffle history record has not been written")]
+    HistoryNotWritten,
+
+    #[msg("Mint symbols must be 1-10 upper-case letters or digits and not SOL")]
+    InvalidSymbol,
+
+    #[msg("Token raffles need the vault, token program and token accounts")]
+    MissingTokenAccounts,
+
+    #[msg("The token account does not belong to the expected owner and mint")]
+    InvalidTokenAccount,
+
+    #[msg("The mint uses a Token-2022 extension that raffles cannot support")]
+    UnsupportedMint,
+
+    #[msg("The prize mint must have a supply of 1 and no decimals")]
+    NotAnNft,
+
+    #[msg("The raffle has no prize in escrow")]
+    NoPrizeEscrowed,
+
+    #[msg("The prize must be released before the raffle is closed")]
+    PrizeStillEscrowed,
+
+    #[msg("A raffle's prize is the pot, an NFT or a fixed amount; a sales share needs a fixed prize")]
+    InvalidPrizeConfig,
+
+    #[msg("Prize splits need 1-10 non-zero shares adding up to 10000 bps")]
+    InvalidPrizeSplit,
+
+    #[msg("The raffle has no such place")]
+    InvalidPlace,
+
+    #[msg("The oracle moved past the requested round; refunds open after the grace period")]
+    RandomnessRoundMissed,
+
+    #[msg("The raffle was created with the current layout")]
+    NotLegacyRaffle,
+
+    #[msg("The history record belongs to a different raffle or winner")]
+    HistoryRecordMismatch,
+
+    #[msg("The ticket is already in the ledger")]
+    TicketAlreadyMigrated,
+
+    #[msg("Raffle names are at most 64 bytes")]
+    NameTooLong,
+
+    #[msg("Raffle descriptions are at most 256 bytes")]
+    DescriptionTooLong,
 }
 
-// Helper function to get the week number from a timestamp
-// Weeks start from Unix epoch and increment every 7 days
-fn get_week_number(timestamp: i64) -> u32 {
-    // Each week is 7 days * 24 hours * 60 minutes * 60 seconds
-    const SECONDS_PER_WEEK: i64 = 7 * 24 * 60 * 60;
-    (timestamp / SECONDS_PER_WEEK) as u32
+// Sell `quantity` consecutive tickets to the buyer and append them to the ticket ledger
+fn purchase_tickets(ctx: Context<BuyTicket>, chunk_index: u32, quantity: u32) -> Result<()> {
+    let raffle = &mut ctx.accounts.raffle;
+    let buyer = &ctx.accounts.buyer;
+    let ticket_ledger = &mut ctx.accounts.ticket_ledger;
+    let user_stats = &mut ctx.accounts.user_stats;
+    let participant_flag = &mut ctx.accounts.participant_flag;
+    let clock = Clock::get()?;
+
+    require!(quantity > 0, RaffleError::InvalidTicketCount);
+
+    // Check if raffle is active, not ended and has room for the whole batch
+    raffle.require_status(RaffleAction::BuyTickets)?;
+    require!(clock.unix_timestamp < raffle.end_timestamp, RaffleError::RaffleEnded);
+    let tickets_after = raffle.total_tickets.checked_add(quantity).ok_or(RaffleError::RaffleFull)?;
+    require!(tickets_after <= raffle.max_tickets, RaffleError::RaffleFull);
+
+    // Enforce the per-wallet cap across all of the buyer's purchases
+    let wallet_tickets = participant_flag.ticket_count.checked_add(quantity)
+        .ok_or(RaffleError::WalletTicketLimitReached)?;
+    require!(
+        raffle.max_tickets_per_wallet == 0 || wallet_tickets <= raffle.max_tickets_per_wallet,
+        RaffleError::WalletTicketLimitReached
+    );
+
+    // Transfer SOL (or tokens) from buyer to raffle account (or vault), once for the whole batch
+    let total_price = raffle.ticket_price.checked_mul(quantity as u64).ok_or(RaffleError::InvalidTicketCount)?;
+    let amount_credited = collect_payment(
+        raffle,
+        buyer,
+        ctx.accounts.buyer_token_account.as_ref(),
+        ctx.accounts.vault.as_mut(),
+        ctx.accounts.mint.as_ref(),
+        ctx.accounts.token_program.as_ref(),
+        total_price,
+    )?;
+    if raffle.is_token_raffle() {
+        raffle.pot += amount_credited;
+    }
+
+    // Numbers are handed out from the first free run after the tickets sold so far; raffles
+    // that still hold legacy randomly-numbered tickets fall back to filling gaps
+    let start = if raffle.total_tickets < raffle.max_tickets { raffle.total_tickets + 1 } else { 1 };
+    let first_number = find_free_run(&raffle.used_numbers, raffle.max_tickets, quantity, start)
+        .ok_or(RaffleError::NoContiguousTickets)?;
+    for number in first_number..first_number + quantity {
+        mark_ticket_used(&mut raffle.used_numbers, number);
+    }
+
+    let raffle_key = raffle.key();
+    ticket_ledger.set_up(raffle_key, buyer.key(), chunk_index);
+    ticket_ledger.insert(LedgerEntry {
+        buyer: buyer.key(),
+        first_number,
+        count: quantity,
+        refunded: false,
+        amount_paid: amount_credited,
+    })?;
+    raffle.ledger_entries += 1;
+
+    // Track unique participants and update user statistics for the leaderboard
+    let new_entrant = record_participant(raffle, participant_flag, raffle_key, buyer.key());
+    participant_flag.ticket_count = wallet_tickets;
+    participant_flag.amount_paid += amount_credited;
+    if user_stats.user == Pubkey::default() {
+        set_up_user_stats(user_stats, buyer.key(), clock.unix_timestamp);
+    }
+    let lamports_spent = if raffle.is_token_raffle() { 0 } else { total_price };
+    record_ticket_purchase(user_stats, quantity, lamports_spent, new_entrant, clock.unix_timestamp);
+
+    raffle.total_tickets += quantity;
+
+    // Check if raffle is now sold out, if so, close sales so the draw can be requested right away
+    close_sales_if_sold_out(raffle);
+
+    msg!("Tickets #{}-#{} purchased by: {}", first_number, first_number + quantity - 1, buyer.key());
+    Ok(())
 }
 
-// Helper function to get the month number from a timestamp
-// This is a simplified version that assumes 30 days per month
-fn get_month_number(timestamp: i64) -> u32 {
-    // Each month is approximately 30 days * 24 hours * 60 minutes * 60 seconds
-    const SECONDS_PER_MONTH: i64 = 30 * 24 * 60 * 60;
-    (timestamp / SECONDS_PER_MONTH) as u32
+// Count a wallet's first entry into a raffle; returns whether this was it
+fn record_participant(raffle: &mut Raffle, participant_flag: &mut ParticipantFlag, raffle_key: Pubkey, buyer: Pubkey) -> bool {
+    if participant_flag.raffle != Pubkey::default() {
+        return false;
+    }
+
+    participant_flag.raffle = raffle_key;
+    participant_flag.participant = buyer;
+
+    raffle.unique_entrants += 1;
+    msg!("New unique entrant: {}. Total unique entrants: {}", buyer, raffle.unique_entrants);
+    true
+}
+
+// Grow a program account created with an older, shorter layout to `space` bytes, topping up
+// its rent from `payer`. New bytes are zeroed, which is what appended fields start from. Only
+// the extra rent is paid in, since lamports above rent (e.g. a raffle's pot) are not the account's to keep.
+fn grow_account<'info>(account: &AccountInfo<'info>, payer: &AccountInfo<'info>, space: usize) -> Result<()> {
+    if account.data_len() >= space {
+        return Ok(());
+    }
+
+    let rent = Rent::get()?;
+    let top_up = rent.minimum_balance(space).saturating_sub(rent.minimum_balance(account.data_len()));
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone()],
+        )?;
+    }
+    account.realloc(space, true)?;
+    Ok(())
+}
+
+// Load user stats of any layout, first growing them to the current one at the payer's expense
+fn load_grown_user_stats<'info>(user_stats: &AccountInfo<'info>, payer: &AccountInfo<'info>) -> Result<UserStats> {
+    require!(
+        user_stats.try_borrow_data()?.starts_with(UserStats::DISCRIMINATOR),
+        anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+    );
+
+    grow_account(user_stats, payer, UserStats::SPACE)?;
+    UserStats::try_deserialize(&mut &user_stats.try_borrow_data()?[..])
+}
+
+// Start a user's stats with empty counters in the current week and month
+fn set_up_user_stats(user_stats: &mut UserStats, user: Pubkey, now: i64) {
+    user_stats.user = user;
+    user_stats.total_tickets_purchased = 0;
+    user_stats.weekly_tickets = 0;
+    user_stats.monthly_tickets = 0;
+
+    // Get current ISO week (yyyyww) and calendar month (yyyymm)
+    user_stats.current_week = get_week_number(now);
+    user_stats.current_month = get_month_number(now);
+
+    user_stats.version = USER_STATS_VERSION;
+    user_stats.total_spent = 0;
+    user_stats.raffles_entered = 0;
+    user_stats.raffles_won = 0;
+    user_stats.total_won = 0;
+    user_stats.biggest_win = 0;
+    user_stats.last_activity = now;
+
+    msg!("User stats initialized for {}", user);
+}
+
+// Add purchased tickets to the buyer's all-time, weekly and monthly counters and lifetime spend
+fn record_ticket_purchase(user_stats: &mut UserStats, quantity: u32, amount_paid: u64, new_entrant: bool, now: i64) {
+    user_stats.total_tickets_purchased += quantity;
+    user_stats.total_spent += amount_paid;
+    if new_entrant {
+        user_stats.raffles_entered += 1;
+    }
+    user_stats.last_activity = now;
+
+    // Accounts created before calendar periods still hold epoch-based period numbers
+    user_stats.current_week = migrate_week_number(user_stats.current_week);
+    user_stats.current_month = migrate_month_number(user_stats.current_month);
+
+    // Reset the period counters when a new week or month has started
+    let current_week = get_week_number(now);
+    let current_month = get_month_number(now);
+
+    if user_stats.current_week != current_week {
+        user_stats.current_week = current_week;
+        user_stats.weekly_tickets = 0;
+    }
+
+    if user_stats.current_month != current_month {
+        user_stats.current_month = current_month;
+        user_stats.monthly_tickets = 0;
+    }
+
+    user_stats.weekly_tickets += quantity;
+    user_stats.monthly_tickets += quantity;
+}
+
+// Add a claimed prize to the winner's lifetime stats; a raffle counts as won once, however many
+// of its places the winner claims
+fn record_win(user_stats: &mut UserStats, amount: u64, first_win_in_raffle: bool, now: i64) {
+    if first_win_in_raffle {
+        user_stats.raffles_won += 1;
+    }
+    user_stats.total_won += amount;
+    user_stats.biggest_win = user_stats.biggest_win.max(amount);
+    user_stats.last_activity = now;
+}
+
+// Move the user to their place in a top-N table sorted by tickets, best first. Ties keep the
+// earlier player ahead; a player who doesn't beat the last place of a full table is left out.
+fn update_ranking(table: &mut Vec<RankEntry>, capacity: usize, user: Pubkey, tickets: u32) {
+    match table.iter().position(|entry| entry.user == user) {
+        Some(index) => table[index].tickets = tickets,
+        None if tickets == 0 => return,
+        None if table.len() < capacity => table.push(RankEntry { user, tickets }),
+        None => match table.last_mut() {
+            Some(last) if tickets > last.tickets => *last = RankEntry { user, tickets },
+            _ => return,
+        },
+    }
+
+    // Stable sort, so ties stay in the order they were reached
+    table.sort_by_key(|entry| std::cmp::Reverse(entry.tickets));
+}
+
+// A player's ticket counts for the running week and month; counts of a past period are zero
+fn current_period_tickets(user_stats: &UserStats, now: i64) -> (u32, u32) {
+    let weekly_tickets = if migrate_week_number(user_stats.current_week) == get_week_number(now) {
+        user_stats.weekly_tickets
+    } else {
+        0
+    };
+    let monthly_tickets = if migrate_month_number(user_stats.current_month) == get_month_number(now) {
+        user_stats.monthly_tickets
+    } else {
+        0
+    };
+    (weekly_tickets, monthly_tickets)
+}
+
+// Record the single prize of a legacy raffle as claimed if the history record is its claim's
+fn mark_claimed_from_history(raffle: &mut Raffle, history: &RaffleHistory) -> Result<()> {
+    require!(raffle.fee_bps.is_none(), RaffleError::NotLegacyRaffle);
+    require!(raffle.current_status() == RaffleStatus::Drawn && raffle.claimed_places == 0,
+             RaffleError::PrizeAlreadyClaimed);
+    require!(
+        history.raffle_id == raffle.raffle_id && raffle.winner == Some(history.winner_ticket),
+        RaffleError::HistoryRecordMismatch
+    );
+
+    raffle.claimed_places = 1;
+    raffle.set_status(RaffleStatus::Claimed)
+}
+
+// The longest prefix of `text` that fits in `max_len` bytes without splitting a character
+fn truncate_text(text: &str, max_len: usize) -> String {
+    let mut end = text.len().min(max_len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
+// Whether a legacy raffle still holds its pot on top of its rent; the original claim_prize paid
+// the whole pot out, leaving only the rent
+fn holds_legacy_pot(balance: u64, rent_exempt: u64, total_pot: u64) -> bool {
+    total_pot > 0 && balance >= rent_exempt.saturating_add(total_pot)
+}
+
+// Stop sales once every ticket is sold so the draw can be requested right away
+fn close_sales_if_sold_out(raffle: &mut Raffle) {
+    if raffle.total_tickets == raffle.max_tickets {
+        raffle.is_active = false;
+        msg!("Raffle sold out! Request randomness to draw the winner");
+    }
+}
+
+// Mark a ticket number (1-based) as sold in the bitmap
+fn mark_ticket_used(used_numbers: &mut [u8], ticket_number: u32) {
+    let bitmap_index = (ticket_number - 1) as usize;
+    used_numbers[bitmap_index / BITS_PER_BYTE] |= 1u8 << (bitmap_index % BITS_PER_BYTE);
+}
+
+// Check whether a ticket number (1-based) is already sold
+fn is_ticket_used(used_numbers: &[u8], ticket_number: u32) -> bool {
+    let bitmap_index = (ticket_number - 1) as usize;
+    (used_numbers[bitmap_index / BITS_PER_BYTE] & (1u8 << (bitmap_index % BITS_PER_BYTE))) != 0
+}
+
+// Find the first number of `quantity` consecutive unused tickets, searching from `start`
+// to the end of the raffle and then from ticket 1. Runs never wrap past max_tickets.
+fn find_free_run(used_numbers: &[u8], max_tickets: u32, quantity: u32, start: u32) -> Option<u32> {
+    let scan = |from: u32, to: u32| {
+        let mut run = 0u32;
+        for number in from..=to {
+            if is_ticket_used(used_numbers, number) {
+                run = 0;
+            } else {
+                run += 1;
+                if run == quantity {
+                    return Some(number + 1 - quantity);
+                }
+            }
+        }
+        None
+    };
+
+    // The second pass only needs to reach runs that start before `start`
+    scan(start, max_tickets).or_else(|| scan(1, (start + quantity - 1).min(max_tickets)))
+}
+
+// A raffle account can be closed once nobody is owed anything from it: a drawn raffle needs
+// every place claimed and its history written, a refunding one every ticket refunded
+fn check_closable(raffle: &Raffle, history_written: bool) -> Result<()> {
+    match raffle.current_status() {
+        RaffleStatus::Cancelled | RaffleStatus::Refunding => {
+            require!(raffle.is_settled(), RaffleError::RaffleNotSettled);
+        }
+        RaffleStatus::Claimed => {
+            require!(history_written, RaffleError::HistoryNotWritten);
+        }
+        RaffleStatus::Active | RaffleStatus::Drawn => return err!(RaffleError::RaffleNotSettled),
+    }
+    // An unclaimed fixed prize is swept back to the creator with the rest of the pot; an NFT
+    // has to be returned first
+    require!(raffle.prize != PrizeKind::Nft || raffle.prize_amount == 0, RaffleError::PrizeStillEscrowed);
+    Ok(())
+}
+
+// Accounts tied to a raffle can be closed once it is settled. A raffle account no longer owned
+// by the program was closed by close_raffle, which already required settlement; raffle
+// addresses are never reused, so it cannot have been re-created.
+fn require_raffle_settled(raffle: &AccountInfo) -> Result<()> {
+    if raffle.owner != &crate::ID {
+        return Ok(());
+    }
+
+    let raffle = Raffle::try_deserialize(&mut &raffle.try_borrow_data()?[..])?;
+    require!(raffle.is_settled(), RaffleError::RaffleNotSettled);
+    Ok(())
+}
+
+// Record a legacy ticket as a single-ticket ledger entry paid at the raffle's ticket price
+fn add_legacy_ticket(ticket_ledger: &mut TicketLedger, raffle: &mut Raffle, ticket: &Ticket) -> Result<()> {
+    require!(ticket_ledger.find_entry(ticket.ticket_number).is_none(), RaffleError::TicketAlreadyMigrated);
+    ticket_ledger.insert(LedgerEntry {
+        buyer: ticket.buyer,
+        first_number: ticket.ticket_number,
+        count: 1,
+        refunded: false,
+        amount_paid: raffle.ticket_price,
+    })?;
+    raffle.ledger_entries += 1;
+    Ok(())
+}
+
+// Split process_refunds' remaining accounts into (ticket ledger, recipient) pairs
+fn refund_pairs<T>(accounts: &[T]) -> Result<Vec<(&T, &T)>> {
+    let pairs = accounts.chunks_exact(2);
+    require!(pairs.remainder().is_empty(), RaffleError::InvalidRefundAccounts);
+    Ok(pairs.map(|pair| (&pair[0], &pair[1])).collect())
+}
+
+// A refund goes to the buyer of a ledger chunk of this raffle: to their wallet for SOL raffles
+// (`mint` is the default key) or to their token account of the raffle mint for token raffles
+fn check_refund_recipient(raffle: &Raffle, raffle_key: Pubkey, ticket_ledger: &TicketLedger,
+                          recipient_owner: Pubkey, mint: Pubkey) -> Result<()> {
+    require!(ticket_ledger.raffle == raffle_key, RaffleError::InvalidRefundAccounts);
+    require!(ticket_ledger.buyer == recipient_owner, RaffleError::InvalidRefundAccounts);
+    require!(mint == raffle.mint, RaffleError::InvalidTokenAccount);
+    Ok(())
+}
+
+// Flag one ledger entry as refunded so it is only refunded once; returns the amount owed
+fn take_refund(entry: &mut LedgerEntry) -> Result<u64> {
+    require!(!entry.refunded, RaffleError::AlreadyRefunded);
+    entry.refunded = true;
+
+    Ok(entry.amount_paid)
+}
+
+// Take a ticket payment from the buyer into the pot: lamports into the raffle account for SOL
+// raffles, tokens from the buyer's token account into the raffle vault for token raffles.
+// Returns the amount the pot was credited with, which is net of any Token-2022 transfer fee.
+fn collect_payment<'info>(
+    raffle: &Account<'info, Raffle>,
+    buyer: &Signer<'info>,
+    buyer_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    vault: Option<&mut InterfaceAccount<'info, TokenAccount>>,
+    mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: Option<&Interface<'info, TokenInterface>>,
+    amount: u64,
+) -> Result<u64> {
+    if !raffle.is_token_raffle() {
+        invoke(
+            &system_instruction::transfer(
+                buyer.key,
+                raffle.to_account_info().key,
+                amount,
+            ),
+            &[
+                buyer.to_account_info(),
+                raffle.to_account_info(),
+            ],
+        )?;
+        return Ok(amount);
+    }
+
+    let (Some(buyer_token_account), Some(vault), Some(mint), Some(token_program)) =
+        (buyer_token_account, vault, mint, token_program) else {
+        return err!(RaffleError::MissingTokenAccounts);
+    };
+    let balance_before = vault.amount;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: buyer_token_account.to_account_info(),
+                mint: mint.to_account_info(),
+                to: vault.to_account_info(),
+                authority: buyer.to_account_info(),
+            },
+        ),
+        amount,
+        mint.decimals,
+    )?;
+
+    // Credit what actually arrived rather than what was sent
+    vault.reload()?;
+    Ok(vault.amount - balance_before)
+}
+
+// Where a payment to `wallet` goes: the wallet itself for SOL raffles, or the wallet's token
+// account of the raffle's mint for token raffles
+fn payment_recipient<'info>(
+    raffle: &Raffle,
+    wallet: &AccountInfo<'info>,
+    token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+) -> Result<AccountInfo<'info>> {
+    if !raffle.is_token_raffle() {
+        return Ok(wallet.clone());
+    }
+
+    let token_account = token_account.ok_or(RaffleError::MissingTokenAccounts)?;
+    require!(token_account.owner == wallet.key(), RaffleError::InvalidTokenAccount);
+    require!(token_account.mint == raffle.mint, RaffleError::InvalidTokenAccount);
+    Ok(token_account.to_account_info())
+}
+
+// Pay `amount` out of the pot to a recipient from payment_recipient: lamports from the raffle
+// account for SOL raffles, tokens from the vault (signed for by the raffle PDA) for token raffles.
+// Zero amounts are skipped, so a recipient owed nothing is never touched
+fn pay_from_pot<'info>(
+    raffle: &Account<'info, Raffle>,
+    vault: Option<&InterfaceAccount<'info, TokenAccount>>,
+    mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: Option<&Interface<'info, TokenInterface>>,
+    recipient: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    if !raffle.is_token_raffle() {
+        **raffle.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **recipient.try_borrow_mut_lamports()? += amount;
+        return Ok(());
+    }
+
+    let (Some(vault), Some(mint), Some(token_program)) = (vault, mint, token_program) else {
+        return err!(RaffleError::MissingTokenAccounts);
+    };
+    let index = raffle.index.to_le_bytes();
+    let bump = [raffle.bump];
+    let raffle_seeds: &[&[u8]] = &[b"raffle", &index, &bump];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: recipient.clone(),
+                authority: raffle.to_account_info(),
+            },
+            &[raffle_seeds],
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// Send the escrowed prize to the recipient's token account and close the prize vault, returning
+// its rent to the creator. The vault is signed for by the raffle PDA.
+fn release_prize<'info>(
+    raffle: &mut Account<'info, Raffle>,
+    prize_vault: &InterfaceAccount<'info, TokenAccount>,
+    prize_mint: &InterfaceAccount<'info, Mint>,
+    token_program: Option<&Interface<'info, TokenInterface>>,
+    recipient: &InterfaceAccount<'info, TokenAccount>,
+    creator: &AccountInfo<'info>,
+) -> Result<()> {
+    let token_program = token_program.ok_or(RaffleError::MissingTokenAccounts)?;
+    require!(recipient.mint == raffle.prize_mint, RaffleError::InvalidTokenAccount);
+
+    let index = raffle.index.to_le_bytes();
+    let bump = [raffle.bump];
+    let raffle_seeds: &[&[u8]] = &[b"raffle", &index, &bump];
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: prize_vault.to_account_info(),
+                mint: prize_mint.to_account_info(),
+                to: recipient.to_account_info(),
+                authority: raffle.to_account_info(),
+            },
+            &[raffle_seeds],
+        ),
+        prize_vault.amount,
+        prize_mint.decimals,
+    )?;
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: prize_vault.to_account_info(),
+            destination: creator.clone(),
+            authority: raffle.to_account_info(),
+        },
+        &[raffle_seeds],
+    ))?;
+
+    raffle.prize_amount = 0;
+    Ok(())
+}
+
+// Mints owned by the legacy token program have no extensions; Token-2022 mints may only use
+// the extensions in SUPPORTED_MINT_EXTENSIONS
+fn require_supported_mint(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    let extensions = state.get_extension_types()?;
+    require!(supports_mint_extensions(&extensions), RaffleError::UnsupportedMint);
+    Ok(())
+}
+
+pub fn supports_mint_extensions(extensions: &[ExtensionType]) -> bool {
+    extensions.iter().all(|extension| SUPPORTED_MINT_EXTENSIONS.contains(extension))
+}
+
+// Whether a Token-2022 mint charges a transfer fee, which leaves withheld tokens in the vault
+fn has_transfer_fee(mint: &AccountInfo) -> Result<bool> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(state.get_extension::<TransferFeeConfig>().is_ok())
+}
+
+// Symbols are short upper-case tickers; "SOL" is reserved for SOL raffles
+pub fn is_valid_symbol(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && symbol.len() <= MAX_SYMBOL_LEN
+        && symbol.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+        && symbol != "SOL"
+}
+
+// Validate creation parameters, assign the next raffle number and ID and fill in a new raffle
+fn set_up_raffle(
+    raffle: &mut Raffle,
+    counter: &mut ProgramCounter,
+    config: &PlatformConfig,
+    authority: Pubkey,
+    bump: u8,
+    symbol: &str,
+    params: RaffleParams,
+) -> Result<()> {
+    let RaffleParams {
+        name,
+        description,
+        ticket_price,
+        duration_hours,
+        max_tickets,
+        commitment,
+        min_tickets,
+        max_tickets_per_wallet,
+        fixed_prize,
+        sales_share_bps,
+        prize_split_bps,
+    } = params;
+    let clock = Clock::get()?;
+    
+    // Validate inputs
+    require!(duration_hours > 0, RaffleError::InvalidDuration);
+    require!(duration_hours <= 30 * 24, RaffleError::DurationTooLong); // Max 30 days
+    require!(max_tickets > 0, RaffleError::InvalidTicketCount);
+    require!(ticket_price > 0, RaffleError::InvalidTicketPrice);
+    require!(max_tickets <= MAX_BITMAP_SIZE as u32, RaffleError::TooManyTickets);
+    require!(name.len() <= MAX_NAME_LEN, RaffleError::NameTooLong);
+    require!(description.len() <= MAX_DESCRIPTION_LEN, RaffleError::DescriptionTooLong);
+    let min_tickets = min_tickets.unwrap_or(0);
+    require!(min_tickets <= max_tickets, RaffleError::InvalidTicketCount);
+    let max_tickets_per_wallet = max_tickets_per_wallet.unwrap_or(0);
+    require!(max_tickets_per_wallet <= max_tickets, RaffleError::InvalidTicketCount);
+    require!(fixed_prize.is_some() || sales_share_bps.is_none(), RaffleError::InvalidPrizeConfig);
+    require!(fixed_prize.is_none_or(|prize| prize > 0), RaffleError::InvalidPrizeConfig);
+    let sales_share_bps = sales_share_bps.unwrap_or(0);
+    require!(sales_share_bps <= BPS_DENOMINATOR, RaffleError::InvalidPrizeConfig);
+    let prize_split_bps = prize_split_bps.unwrap_or_default();
+    require!(prize_split_bps.is_empty() || is_valid_prize_split(&prize_split_bps), RaffleError::InvalidPrizeSplit);
+    require!(prize_split_bps.len() as u32 <= max_tickets, RaffleError::InvalidPrizeSplit);
+    // Every place needs a ticket, so a raffle with N winners needs at least N tickets sold
+    let min_tickets = min_tickets.max(prize_split_bps.len() as u32);
+    
+    // Increment counter and generate unique ID
+    counter.raffle_count += 1;
+    let id_number = format!("{:03}", counter.raffle_count);
+    let raffle_id = format!("7F-{}-{}", symbol, id_number);
+    
+    // Set up raffle parameters
+    raffle.authority = authority;
+    raffle.name = name;
+    raffle.description = description;
+    raffle.ticket_price = ticket_price;
+    raffle.start_timestamp = clock.unix_timestamp;
+    raffle.end_timestamp = clock.unix_timestamp + (duration_hours * 3600) as i64; // Convert hours to seconds
+    raffle.max_tickets = max_tickets;
+    raffle.total_tickets = 0;
+    raffle.min_tickets = min_tickets;
+    raffle.max_tickets_per_wallet = max_tickets_per_wallet;
+    raffle.is_active = true;
+    raffle.status = RaffleStatus::Active;
+    raffle.winner = None;
+    raffle.raffle_id = raffle_id;
+    raffle.index = counter.raffle_count;
+    raffle.bump = bump;
+    if fixed_prize.is_some() {
+        raffle.prize = PrizeKind::Fixed;
+        raffle.sales_share_bps = sales_share_bps;
+    }
+    if prize_split_bps.len() > 1 {
+        msg!("{} winners, prize split {:?} bps", prize_split_bps.len(), prize_split_bps);
+    }
+    raffle.prize_split_bps = prize_split_bps;
+
+    // The fee is fixed for the raffle's lifetime so buyers know it up front
+    raffle.fee_bps = Some(config.default_fee_bps);
+
+    // A commitment switches the raffle to the oracle-free commit-reveal draw
+    match commitment {
+        Some(commitment) => {
+            raffle.draw_mode = DrawMode::CommitReveal;
+            raffle.commitment = commitment;
+        }
+        None => raffle.draw_mode = DrawMode::Oracle,
+    }
+    
+    // Initialize the bitmap for tracking used ticket numbers using bit packing (8 tickets per byte)
+    // Calculate how many bytes we need: ceiling of max_tickets / 8
+    let bytes_needed = (max_tickets as usize).div_ceil(BITS_PER_BYTE);
+    raffle.used_numbers = vec![0u8; bytes_needed];
+    
+    msg!("Platform fee: {} bps", config.default_fee_bps);
+    if min_tickets > 0 {
+        msg!("Refunds open if fewer than {} tickets are sold", min_tickets);
+    }
+    if max_tickets_per_wallet > 0 {
+        msg!("Each wallet can buy at most {} tickets", max_tickets_per_wallet);
+    }
+    if let Some(fixed_prize) = fixed_prize {
+        msg!("Guaranteed prize: {} plus {} bps of sales", fixed_prize, sales_share_bps);
+    }
+    Ok(())
+}
+
+// How a raffle pot is paid out
+#[derive(Debug, PartialEq, Eq)]
+pub struct Payout {
+    pub winner_amount: u64,
+    pub platform_fee: u64,
+}
+
+// Split a pot into the platform fee and the winner's prize. The fee is rounded down and the
+// rounding remainder goes to the winner, so the two parts always add up to the whole pot.
+pub fn split_pot(pot: u64, fee_bps: u16) -> Payout {
+    let fee_bps = fee_bps.min(BPS_DENOMINATOR);
+    let platform_fee = (pot as u128 * fee_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+
+    Payout {
+        winner_amount: pot - platform_fee,
+        platform_fee,
+    }
+}
+
+// A prize split has one share per place, at most MAX_WINNERS, each non-zero, adding up to 100%
+pub fn is_valid_prize_split(split_bps: &[u16]) -> bool {
+    (1..=MAX_WINNERS).contains(&split_bps.len())
+        && split_bps.iter().all(|&share| share > 0)
+        && split_bps.iter().map(|&share| share as u32).sum::<u32>() == BPS_DENOMINATOR as u32
+}
+
+// Split a prize between places. Shares are rounded down and the rounding remainder goes to
+// first place, so the shares always add up to the whole prize.
+pub fn split_prize_tiers(prize: u64, split_bps: &[u16]) -> Vec<u64> {
+    let mut shares: Vec<u64> = split_bps.iter()
+        .map(|&share| (prize as u128 * share as u128 / BPS_DENOMINATOR as u128) as u64)
+        .collect();
+    let remainder = prize - shares.iter().sum::<u64>();
+    if let Some(first) = shares.first_mut() {
+        *first += remainder;
+    }
+    shares
+}
+
+// How the sales of a fixed-prize raffle are shared once the fee is taken
+#[derive(Debug, PartialEq, Eq)]
+pub struct SalesSplit {
+    pub winner_share: u64,
+    pub creator_share: u64,
+}
+
+// Split sales between the winner and the creator. The winner's share is rounded down and the
+// remainder goes to the creator.
+pub fn split_sales(sales: u64, winner_share_bps: u16) -> SalesSplit {
+    let winner_share_bps = winner_share_bps.min(BPS_DENOMINATOR);
+    let winner_share = (sales as u128 * winner_share_bps as u128 / BPS_DENOMINATOR as u128) as u64;
+
+    SalesSplit {
+        winner_share,
+        creator_share: sales - winner_share,
+    }
+}
+
+// Lamports in the prize vault above its rent-exempt minimum
+fn prize_vault_balance(prize_vault: &AccountInfo) -> Result<u64> {
+    let rent_exempt = Rent::get()?.minimum_balance(prize_vault.data_len());
+    Ok(prize_vault.lamports().saturating_sub(rent_exempt))
+}
+
+// Pay a leaderboard prize out of the vault without touching its rent-exempt reserve
+fn pay_from_prize_vault(prize_vault: &mut Account<PrizeVault>, recipient: &AccountInfo, amount: u64) -> Result<()> {
+    require!(amount <= prize_vault_balance(&prize_vault.to_account_info())?, RaffleError::InvalidPrizeAmount);
+
+    **prize_vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+    **recipient.try_borrow_mut_lamports()? += amount;
+    prize_vault.total_paid += amount;
+    Ok(())
+}
+
+// How a platform fee is shared between the treasury and the leaderboard prize pools
+#[derive(Debug, PartialEq, Eq)]
+pub struct FeeSplit {
+    pub treasury: u64,
+    pub weekly_pool: u64,
+    pub monthly_pool: u64,
+}
+
+// Split a platform fee into the weekly and monthly pool shares and the treasury's part. Pool
+// shares are rounded down and the remainder goes to the treasury.
+pub fn split_platform_fee(fee: u64, weekly_pool_bps: u16, monthly_pool_bps: u16) -> FeeSplit {
+    let share = |bps: u16| (fee as u128 * bps.min(BPS_DENOMINATOR) as u128 / BPS_DENOMINATOR as u128) as u64;
+    let weekly_pool = share(weekly_pool_bps);
+    let monthly_pool = share(monthly_pool_bps).min(fee - weekly_pool);
+
+    FeeSplit {
+        treasury: fee - weekly_pool - monthly_pool,
+        weekly_pool,
+        monthly_pool,
+    }
+}
+
+// Split the monthly prize by MONTHLY_PRIZE_DISTRIBUTION, rounding as split_prize_tiers does
+pub fn split_monthly_prize(prize: u64) -> [u64; 3] {
+    let mut shares = MONTHLY_PRIZE_DISTRIBUTION.map(|percent| (prize as u128 * percent as u128 / 100) as u64);
+    shares[0] += prize - shares.iter().sum::<u64>();
+    shares
+}
+
+// Draw the winners from the randomness (Active -> Drawn) and store the proof so anyone can
+// re-check the draw
+fn record_draw(raffle: &mut Raffle, randomness: [u8; 32]) -> Result<()> {
+    let winning_index = winning_index_from_randomness(&randomness, raffle.total_tickets);
+    let winners = draw_winning_tickets(
+        &raffle.used_numbers,
+        raffle.max_tickets,
+        raffle.total_tickets,
+        &randomness,
+        raffle.prize_tiers().len(),
+    ).ok_or(RaffleError::NoAvailableTickets)?;
+
+    // Also closes sales
+    raffle.set_status(RaffleStatus::Drawn)?;
+
+    raffle.randomness = randomness;
+    raffle.winning_index = Some(winning_index);
+    raffle.winner = Some(winners[0]);
+    raffle.winners = winners;
+    Ok(())
+}
+
+// Draw `count` distinct winning tickets, one per place, without replacement. First place uses the
+// randomness as-is (so single-winner draws are unchanged); place k uses hash(randomness, k) and
+// picks among the sold tickets not drawn yet, in ticket-number order.
+pub fn draw_winning_tickets(
+    used_numbers: &[u8],
+    max_tickets: u32,
+    total_tickets: u32,
+    randomness: &[u8; 32],
+    count: usize,
+) -> Option<Vec<u32>> {
+    let mut remaining = used_numbers.to_vec();
+    let mut winners = Vec::with_capacity(count);
+    for place in 0..count as u32 {
+        let tickets_left = total_tickets.checked_sub(place).filter(|&left| left > 0)?;
+        let seed = if place == 0 {
+            *randomness
+        } else {
+            hashv(&[randomness, &place.to_le_bytes()]).to_bytes()
+        };
+
+        let index = winning_index_from_randomness(&seed, tickets_left);
+        let ticket = nth_sold_ticket(&remaining, max_tickets, index)?;
+        let bit = (ticket - 1) as usize;
+        remaining[bit / BITS_PER_BYTE] &= !(1u8 << (bit % BITS_PER_BYTE));
+        winners.push(ticket);
+    }
+    Some(winners)
+}
+
+// Oracle randomness counts only if it is the first round fulfilled after the request; a later
+// round could have been waited for
+fn check_fulfilment(requested_round: u64, round: u64) -> Result<()> {
+    require!(round > requested_round, RaffleError::RandomnessNotFulfilled);
+    require!(round == requested_round + 1, RaffleError::RandomnessRoundMissed);
+    Ok(())
+}
+
+// A raffle can be drawn once its end time has passed or every ticket is sold
+fn is_ready_to_draw(raffle: &Raffle, now: i64) -> bool {
+    now >= raffle.end_timestamp || raffle.total_tickets == raffle.max_tickets
+}
+
+// Reduce 32 bytes of randomness to a position among the sold tickets
+fn winning_index_from_randomness(randomness: &[u8; 32], total_tickets: u32) -> u32 {
+    let value = u64::from_le_bytes(randomness[..8].try_into().unwrap());
+    (value % total_tickets as u64) as u32
+}
+
+// Look up the hash of the first slot at or after `slot` in the SlotHashes sysvar, so a skipped
+// target slot still resolves deterministically to the next produced block.
+// Layout: u64 entry count, then (u64 slot, [u8; 32] hash) entries, newest first
+fn find_slot_hash(data: &[u8], slot: u64) -> Result<[u8; 32]> {
+    const ENTRY_SIZE: usize = 8 + 32;
+
+    require!(data.len() >= 8, RaffleError::SlotHashUnavailable);
+    let count = u64::from_le_bytes(data[..8].try_into().unwrap()) as usize;
+
+    let mut candidate = None;
+    for entry in data[8..].chunks_exact(ENTRY_SIZE).take(count) {
+        let entry_slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if entry_slot < slot {
+            // Seeing an older entry proves the candidate is the earliest one at or after `slot`
+            return candidate.map(|(_, slot_hash)| slot_hash).ok_or_else(|| error!(RaffleError::SlotHashUnavailable));
+        }
+        let mut slot_hash = [0u8; 32];
+        slot_hash.copy_from_slice(&entry[8..]);
+        candidate = Some((entry_slot, slot_hash));
+    }
+
+    // The oldest entry is the target itself; otherwise the target has aged out of the sysvar
+    // (an earlier block may have been skipped) or has not been produced yet
+    match candidate {
+        Some((oldest_slot, slot_hash)) if oldest_slot == slot => Ok(slot_hash),
+        _ => err!(RaffleError::SlotHashUnavailable),
+    }
+}
+
+// Find the ticket number (1-based) of the n-th sold ticket (0-based) in the bitmap
+fn nth_sold_ticket(used_numbers: &[u8], max_tickets: u32, n: u32) -> Option<u32> {
+    let mut seen = 0u32;
+    for i in 0..max_tickets {
+        let byte_index = i as usize / BITS_PER_BYTE;
+        let bit_mask = 1u8 << (i as usize % BITS_PER_BYTE);
+
+        if byte_index < used_numbers.len() && (used_numbers[byte_index] & bit_mask) != 0 {
+            if seen == n {
+                return Some(i + 1);
+            }
+            seen += 1;
+        }
+    }
+    None
+}
+
+// Helper function to get the week number from a timestamp
+// Weeks are ISO 8601 weeks (starting on Monday, UTC), numbered yyyyww
+fn get_week_number(timestamp: i64) -> u32 {
+    let (year, week) = iso_week(timestamp);
+    (year * 100) as u32 + week
+}
+
+// Helper function to get the month number from a timestamp
+// Months are calendar months (UTC), numbered yyyymm
+fn get_month_number(timestamp: i64) -> u32 {
+    let (year, month, _) = civil_from_days(timestamp.div_euclid(SECONDS_PER_DAY));
+    (year * 100) as u32 + month
+}
+
+// Map a week number stored before calendar periods (7-day weeks since the epoch) to the
+// ISO week its first day falls in; current numbers are returned unchanged
+fn migrate_week_number(week: u32) -> u32 {
+    if week >= LEGACY_PERIOD_LIMIT {
+        return week;
+    }
+    get_week_number(week as i64 * 7 * SECONDS_PER_DAY)
+}
+
+// Map a month number stored before calendar periods (30-day months since the epoch) to the
+// calendar month its first day falls in; current numbers are returned unchanged
+fn migrate_month_number(month: u32) -> u32 {
+    if month >= LEGACY_PERIOD_LIMIT {
+        return month;
+    }
+    get_month_number(month as i64 * 30 * SECONDS_PER_DAY)
+}
+
+// ISO 8601 (year, week) of a timestamp. The week belongs to the year of its Thursday,
+// so early January days can fall in week 52/53 of the previous year.
+pub fn iso_week(timestamp: i64) -> (i64, u32) {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    // 1970-01-01 was a Thursday; Monday = 0
+    let weekday = (days + 3).rem_euclid(7);
+    let thursday = days - weekday + 3;
+
+    let (year, _, _) = civil_from_days(thursday);
+    let week = (thursday - days_from_civil(year, 1, 1)) / 7 + 1;
+    (year, week as u32)
+}
+
+// Convert days since 1970-01-01 to a proleptic Gregorian (year, month, day).
+// Howard Hinnant's civil_from_days: counts 400-year eras of years starting on March 1st,
+// so the leap day is the last day of its year.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153; // March = 0
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Convert a proleptic Gregorian date to days since 1970-01-01 (inverse of civil_from_days)
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = ((month + 9) % 12) as i64; // March = 0
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ALL_STATUSES: [RaffleStatus; 5] = [
+        RaffleStatus::Active,
+        RaffleStatus::Drawn,
+        RaffleStatus::Claimed,
+        RaffleStatus::Cancelled,
+        RaffleStatus::Refunding,
+    ];
+
+    // A zeroed raffle with the given ticket numbers sold
+    fn sold_raffle(max_tickets: u32, sold: &[u32]) -> Raffle {
+        let mut raffle = Raffle::try_deserialize_unchecked(&mut &vec![0u8; 8 + size_of::<Raffle>()][..]).unwrap();
+        raffle.max_tickets = max_tickets;
+        raffle.total_tickets = sold.len() as u32;
+        raffle.used_numbers = vec![0u8; (max_tickets as usize).div_ceil(BITS_PER_BYTE)];
+        for number in sold {
+            mark_ticket_used(&mut raffle.used_numbers, *number);
+        }
+        raffle
+    }
+
+    // Account data of a mock oracle randomness account
+    fn mock_randomness(round: u64, value: [u8; 32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        mock_oracle::OracleRandomness { round, value, authority: Pubkey::default() }
+            .try_serialize(&mut data)
+            .unwrap();
+        data
+    }
+
+    fn read_randomness(mut data: Vec<u8>) -> Result<OracleRandomness> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &mock_oracle::ID, false, 0);
+        OracleRandomness::try_read(&account)
+    }
+
+    #[test]
+    fn oracle_draw_uses_the_next_mock_fulfilment() {
+        let mut raffle = sold_raffle(16, &[2, 3, 5, 7, 11]);
+        raffle.randomness_round = 4;
+
+        // Not fulfilled yet
+        let pending = read_randomness(mock_randomness(4, [0; 32])).unwrap();
+        assert_eq!(check_fulfilment(raffle.randomness_round, pending.round), Err(RaffleError::RandomnessNotFulfilled.into()));
+
+        // A later round cannot be waited for
+        let skipped = read_randomness(mock_randomness(6, [9; 32])).unwrap();
+        assert_eq!(check_fulfilment(raffle.randomness_round, skipped.round), Err(RaffleError::RandomnessRoundMissed.into()));
+
+        let mut value = [0u8; 32];
+        value[0] = 8; // 8 % 5 sold: index 3, ticket #7
+        let fulfilled = read_randomness(mock_randomness(5, value)).unwrap();
+        check_fulfilment(raffle.randomness_round, fulfilled.round).unwrap();
+        record_draw(&mut raffle, fulfilled.value).unwrap();
+
+        assert_eq!(raffle.current_status(), RaffleStatus::Drawn);
+        assert_eq!((raffle.winner, raffle.winning_index, raffle.randomness), (Some(7), Some(3), value));
+        assert_eq!(raffle.winners, vec![7]);
+    }
+
+    #[test]
+    fn randomness_accounts_need_the_oracle_discriminator() {
+        assert!(read_randomness(vec![0u8; OracleRandomness::LEN]).is_err());
+
+        let mut data = mock_randomness(1, [1; 32]);
+        data[0] ^= 1;
+        assert!(read_randomness(data).is_err());
+    }
+
+    #[test]
+    fn oracle_discriminator_matches_the_anchor_layout() {
+        assert_eq!(ORACLE_RANDOMNESS_DISCRIMINATOR, mock_oracle::OracleRandomness::DISCRIMINATOR);
+        assert_eq!(ORACLE_RANDOMNESS_DISCRIMINATOR[..], hash(b"account:OracleRandomness").to_bytes()[..8]);
+    }
+
+    #[test]
+    fn free_runs_wrap_around_to_the_first_tickets() {
+        // Tickets 1-2 are free, 3-8 sold, 9-10 free
+        let raffle = sold_raffle(10, &[3, 4, 5, 6, 7, 8]);
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 2, 9), Some(9));
+        // Only one ticket is left from 10 on, so the search wraps to ticket 1
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 2, 10), Some(1));
+        // Runs never wrap across the end into ticket 1
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 3, 9), None);
+    }
+
+    #[test]
+    fn free_runs_fit_exactly_at_the_end() {
+        let raffle = sold_raffle(10, &[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 3, 8), Some(8));
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 3, 1), Some(8));
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 4, 8), None);
+    }
+
+    #[test]
+    fn no_free_run_in_a_fragmented_raffle() {
+        // Every other ticket is sold: single tickets fit, pairs never do
+        let raffle = sold_raffle(10, &[2, 4, 6, 8, 10]);
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 1, 6), Some(7));
+        assert_eq!(find_free_run(&raffle.used_numbers, 10, 2, 1), None);
+        let full = sold_raffle(4, &[1, 2, 3, 4]);
+        assert_eq!(find_free_run(&full.used_numbers, 4, 1, 1), None);
+    }
+
+    // SlotHashes sysvar data holding `slots`, newest first; each hash is filled with its slot's low byte
+    fn slot_hashes_data(slots: &[u64]) -> Vec<u8> {
+        let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+        for slot in slots {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[*slot as u8; 32]);
+        }
+        data
+    }
+
+    #[test]
+    fn slot_hash_of_the_target_or_the_next_produced_block() {
+        let data = slot_hashes_data(&[105, 104, 102, 101, 100]);
+        // Exact match, including the oldest entry in the sysvar
+        assert_eq!(find_slot_hash(&data, 104).unwrap(), [104; 32]);
+        assert_eq!(find_slot_hash(&data, 100).unwrap(), [100; 32]);
+        // Slot 103 was skipped, so the next produced block decides
+        assert_eq!(find_slot_hash(&data, 103).unwrap(), [104; 32]);
+    }
+
+    #[test]
+    fn slot_hash_unavailable_when_aged_out_or_not_produced() {
+        let data = slot_hashes_data(&[105, 104, 102]);
+        // Aged out: slot 101 could have been produced before the oldest entry
+        assert_eq!(find_slot_hash(&data, 101), Err(RaffleError::SlotHashUnavailable.into()));
+        // Not produced yet
+        assert_eq!(find_slot_hash(&data, 106), Err(RaffleError::SlotHashUnavailable.into()));
+        assert_eq!(find_slot_hash(&slot_hashes_data(&[]), 1), Err(RaffleError::SlotHashUnavailable.into()));
+    }
+
+    fn ledger_entry(first_number: u32, count: u32) -> LedgerEntry {
+        LedgerEntry { buyer: player(1), first_number, count, refunded: false, amount_paid: count as u64 * 100 }
+    }
+
+    fn empty_ledger() -> TicketLedger {
+        TicketLedger { raffle: player(9), buyer: player(1), chunk_index: 0, entries: Vec::new() }
+    }
+
+    #[test]
+    fn ledger_lookup_finds_the_range_holding_a_ticket() {
+        let mut ledger = empty_ledger();
+        // Inserted out of order: tickets 5-7, 1-2 and 12
+        ledger.insert(ledger_entry(5, 3)).unwrap();
+        ledger.insert(ledger_entry(1, 2)).unwrap();
+        ledger.insert(ledger_entry(12, 1)).unwrap();
+        let firsts: Vec<u32> = ledger.entries.iter().map(|entry| entry.first_number).collect();
+        assert_eq!(firsts, vec![1, 5, 12]);
+
+        // First and last ticket of each range
+        for (ticket, first) in [(1, 1), (2, 1), (5, 5), (7, 5), (12, 12)] {
+            assert_eq!(ledger.find_entry(ticket).map(|entry| entry.first_number), Some(first));
+        }
+        // Gaps between ranges and tickets past the last one
+        for ticket in [3, 4, 8, 11, 13] {
+            assert!(ledger.find_entry(ticket).is_none());
+        }
+    }
+
+    #[test]
+    fn ledger_chunks_hold_a_fixed_number_of_entries() {
+        let mut ledger = empty_ledger();
+        for index in 0..LEDGER_CHUNK_CAPACITY {
+            ledger.insert(ledger_entry(100 - index * 10, 1)).unwrap();
+        }
+        assert_eq!(ledger.insert(ledger_entry(1, 1)), Err(RaffleError::LedgerChunkFull.into()));
+        assert!(ledger.entries.windows(2).all(|pair| pair[0].first_number < pair[1].first_number));
+        assert_eq!(ledger.find_entry(30).map(|entry| entry.count), Some(1));
+    }
+
+    #[test]
+    fn legacy_tickets_migrate_into_the_ledger_once() {
+        let mut raffle = sold_raffle(16, &[3, 9]);
+        raffle.ticket_price = 250;
+        let mut ledger = empty_ledger();
+        let ticket = Ticket { buyer: player(1), raffle: player(9), ticket_number: 9 };
+
+        add_legacy_ticket(&mut ledger, &mut raffle, &ticket).unwrap();
+        add_legacy_ticket(&mut ledger, &mut raffle, &Ticket { ticket_number: 3, ..ticket.clone() }).unwrap();
+        assert_eq!(raffle.ledger_entries, 2);
+
+        // Same ticket numbers, refundable at the ticket price
+        let entry = ledger.find_entry(9).unwrap();
+        assert_eq!((entry.buyer, entry.first_number, entry.count), (player(1), 9, 1));
+        let entry = ledger.entries.iter_mut().find(|entry| entry.first_number == 3).unwrap();
+        assert_eq!(take_refund(entry).unwrap(), 250);
+
+        assert_eq!(add_legacy_ticket(&mut ledger, &mut raffle, &ticket), Err(RaffleError::TicketAlreadyMigrated.into()));
+        assert_eq!(raffle.ledger_entries, 2);
+    }
+
+    #[test]
+    fn ledger_entries_are_refunded_once() {
+        let mut ledger = empty_ledger();
+        ledger.insert(ledger_entry(1, 4)).unwrap();
+        assert_eq!(take_refund(&mut ledger.entries[0]).unwrap(), 400);
+        assert!(ledger.entries[0].refunded);
+        assert_eq!(take_refund(&mut ledger.entries[0]), Err(RaffleError::AlreadyRefunded.into()));
+    }
+
+    #[test]
+    fn refund_accounts_come_in_ledger_and_recipient_pairs() {
+        let accounts = [player(1), player(2), player(3), player(4)];
+        assert_eq!(refund_pairs(&accounts).unwrap(), vec![(&player(1), &player(2)), (&player(3), &player(4))]);
+        assert!(refund_pairs(&accounts[..0]).unwrap().is_empty());
+        assert_eq!(refund_pairs(&accounts[..3]), Err(RaffleError::InvalidRefundAccounts.into()));
+    }
+
+    #[test]
+    fn refunds_only_go_to_buyers_of_the_raffle() {
+        let raffle = sold_raffle(8, &[1, 2]);
+        let ledger = empty_ledger();
+        let raffle_key = ledger.raffle;
+        check_refund_recipient(&raffle, raffle_key, &ledger, ledger.buyer, Pubkey::default()).unwrap();
+
+        // A ledger of another raffle, or a recipient other than its buyer
+        assert_eq!(check_refund_recipient(&raffle, player(8), &ledger, ledger.buyer, Pubkey::default()),
+                   Err(RaffleError::InvalidRefundAccounts.into()));
+        assert_eq!(check_refund_recipient(&raffle, raffle_key, &ledger, player(2), Pubkey::default()),
+                   Err(RaffleError::InvalidRefundAccounts.into()));
+
+        // Token raffles refund into the buyer's account of the raffle mint
+        let mut token_raffle = sold_raffle(8, &[1, 2]);
+        token_raffle.mint = player(50);
+        check_refund_recipient(&token_raffle, raffle_key, &ledger, ledger.buyer, player(50)).unwrap();
+        assert_eq!(check_refund_recipient(&token_raffle, raffle_key, &ledger, ledger.buyer, player(51)),
+                   Err(RaffleError::InvalidTokenAccount.into()));
+    }
+
+    #[test]
+    fn drawn_raffles_settle_once_every_place_is_claimed() {
+        let mut raffle = sold_raffle(8, &[1, 2, 3, 4]);
+        assert!(!raffle.is_settled());
+        assert_eq!(check_closable(&raffle, true), Err(RaffleError::RaffleNotSettled.into()));
+
+        raffle.prize_split_bps = vec![6_000, 4_000];
+        record_draw(&mut raffle, [5; 32]).unwrap();
+        raffle.claimed_places = 0b01;
+        assert!(!raffle.is_settled());
+        assert_eq!(check_closable(&raffle, true), Err(RaffleError::RaffleNotSettled.into()));
+
+        raffle.claimed_places = 0b11;
+        raffle.set_status(RaffleStatus::Claimed).unwrap();
+        assert!(raffle.is_settled());
+        assert_eq!(check_closable(&raffle, false), Err(RaffleError::HistoryNotWritten.into()));
+        check_closable(&raffle, true).unwrap();
+
+        // An NFT prize has to go back to the creator before the raffle can be closed
+        raffle.prize = PrizeKind::Nft;
+        raffle.prize_amount = 1;
+        assert_eq!(check_closable(&raffle, true), Err(RaffleError::PrizeStillEscrowed.into()));
+    }
+
+    #[test]
+    fn refunding_raffles_settle_once_every_ticket_is_refunded() {
+        let mut raffle = sold_raffle(8, &[1, 2, 3]);
+        raffle.set_status(RaffleStatus::Refunding).unwrap();
+        raffle.refunded_tickets = 2;
+        assert!(!raffle.is_settled());
+        assert_eq!(check_closable(&raffle, false), Err(RaffleError::RaffleNotSettled.into()));
+
+        raffle.refunded_tickets = 3;
+        assert!(raffle.is_settled());
+        check_closable(&raffle, false).unwrap();
+
+        // Cancelled raffles never sold a ticket
+        let mut cancelled = sold_raffle(8, &[]);
+        cancelled.is_active = true;
+        cancelled.set_status(RaffleStatus::Cancelled).unwrap();
+        assert!(cancelled.is_settled());
+        check_closable(&cancelled, false).unwrap();
+    }
+
+    #[test]
+    fn legal_status_transitions() {
+        assert_eq!(RaffleStatus::Active.transition(RaffleStatus::Drawn).unwrap(), RaffleStatus::Drawn);
+        assert_eq!(RaffleStatus::Active.transition(RaffleStatus::Cancelled).unwrap(), RaffleStatus::Cancelled);
+        assert_eq!(RaffleStatus::Active.transition(RaffleStatus::Refunding).unwrap(), RaffleStatus::Refunding);
+        assert_eq!(RaffleStatus::Drawn.transition(RaffleStatus::Claimed).unwrap(), RaffleStatus::Claimed);
+    }
+
+    #[test]
+    fn illegal_status_transitions_are_rejected() {
+        let legal = [
+            (RaffleStatus::Active, RaffleStatus::Drawn),
+            (RaffleStatus::Active, RaffleStatus::Cancelled),
+            (RaffleStatus::Active, RaffleStatus::Refunding),
+            (RaffleStatus::Drawn, RaffleStatus::Claimed),
+        ];
+
+        for from in ALL_STATUSES {
+            for to in ALL_STATUSES {
+                if legal.contains(&(from, to)) {
+                    continue;
+                }
+                assert!(!from.can_transition_to(to), "{:?} -> {:?} should be illegal", from, to);
+                assert!(from.transition(to).is_err(), "{:?} -> {:?} should fail", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn second_claim_reports_already_claimed() {
+        let error = RaffleStatus::Claimed.transition(RaffleStatus::Claimed).unwrap_err();
+        assert_eq!(error, RaffleError::PrizeAlreadyClaimed.into());
+
+        let error = RaffleStatus::Cancelled.transition(RaffleStatus::Claimed).unwrap_err();
+        assert_eq!(error, RaffleError::InvalidRaffleStatus.into());
+    }
+
+    // A raffle with two tickets sold in `status`
+    fn raffle_in(status: RaffleStatus) -> Raffle {
+        let mut raffle = sold_raffle(8, &[1, 2]);
+        raffle.status = status;
+        raffle.is_active = status == RaffleStatus::Active;
+        if matches!(status, RaffleStatus::Drawn | RaffleStatus::Claimed) {
+            raffle.winner = Some(2);
+            raffle.claimed_places = if status == RaffleStatus::Claimed { 1 } else { 0 };
+        }
+        raffle
+    }
+
+    #[test]
+    fn instructions_only_act_in_their_states() {
+        use RaffleAction::*;
+        use RaffleStatus::*;
+        let statuses = [Active, Drawn, Claimed, Cancelled, Refunding];
+        let allowed: [(RaffleAction, &[RaffleStatus]); 6] = [
+            (BuyTickets, &[Active]),
+            (RequestDraw, &[Active]),
+            (Draw, &[Active]),
+            (ClaimPrize, &[Drawn]),
+            (Refund, &[Refunding]),
+            (ReturnPrize, &[Cancelled, Refunding]),
+        ];
+        for (action, states) in allowed {
+            for status in statuses {
+                let result = raffle_in(status).require_status(action);
+                assert_eq!(result.is_ok(), states.contains(&status), "{:?} in {:?}", action, status);
+            }
+        }
+
+        // Sales also stop once an active raffle sells out or ends
+        let mut sold_out = raffle_in(Active);
+        sold_out.is_active = false;
+        assert_eq!(sold_out.require_status(BuyTickets), Err(RaffleError::RaffleNotActive.into()));
+        assert_eq!(raffle_in(Active).require_status(ClaimPrize), Err(RaffleError::NoWinnerDrawn.into()));
+        assert_eq!(raffle_in(Claimed).require_status(ClaimPrize), Err(RaffleError::PrizeAlreadyClaimed.into()));
+        assert_eq!(raffle_in(Refunding).require_status(ClaimPrize), Err(RaffleError::InvalidRaffleStatus.into()));
+
+        // Closing additionally needs everything paid out
+        assert!(check_closable(&raffle_in(Active), true).is_err());
+        assert!(check_closable(&raffle_in(Drawn), true).is_err());
+        assert!(check_closable(&raffle_in(Claimed), true).is_ok());
+        assert!(check_closable(&raffle_in(Refunding), true).is_err());
+    }
+
+    #[test]
+    fn split_pot_examples() {
+        assert_eq!(split_pot(1_000, 500), Payout { winner_amount: 950, platform_fee: 50 });
+        // 5% of 999 is 49.95: the fee rounds down and the 0.95 goes to the winner
+        assert_eq!(split_pot(999, 500), Payout { winner_amount: 950, platform_fee: 49 });
+        assert_eq!(split_pot(0, 500), Payout { winner_amount: 0, platform_fee: 0 });
+        assert_eq!(split_pot(1_000, 0), Payout { winner_amount: 1_000, platform_fee: 0 });
+        assert_eq!(split_pot(1_000, BPS_DENOMINATOR), Payout { winner_amount: 0, platform_fee: 1_000 });
+    }
+
+    proptest! {
+        #[test]
+        fn split_pot_conserves_lamports(pot in any::<u64>(), fee_bps in 0..=BPS_DENOMINATOR) {
+            let payout = split_pot(pot, fee_bps);
+            prop_assert_eq!(payout.winner_amount as u128 + payout.platform_fee as u128, pot as u128);
+        }
+
+        #[test]
+        fn split_pot_fee_is_rounded_down(pot in any::<u64>(), fee_bps in 0..=BPS_DENOMINATOR) {
+            let payout = split_pot(pot, fee_bps);
+            let exact_fee_times_denominator = pot as u128 * fee_bps as u128;
+            prop_assert!(payout.platform_fee as u128 * BPS_DENOMINATOR as u128 <= exact_fee_times_denominator);
+            prop_assert!((payout.platform_fee as u128 + 1) * BPS_DENOMINATOR as u128 > exact_fee_times_denominator);
+        }
+
+        #[test]
+        fn split_pot_never_exceeds_pot(pot in any::<u64>(), fee_bps in any::<u16>()) {
+            let payout = split_pot(pot, fee_bps);
+            prop_assert!(payout.platform_fee <= pot);
+            prop_assert_eq!(payout.winner_amount + payout.platform_fee, pot);
+        }
+
+        #[test]
+        fn split_platform_fee_conserves_lamports(fee in any::<u64>(), weekly_pool_bps in any::<u16>(), monthly_pool_bps in any::<u16>()) {
+            let split = split_platform_fee(fee, weekly_pool_bps, monthly_pool_bps);
+            prop_assert_eq!(
+                split.treasury as u128 + split.weekly_pool as u128 + split.monthly_pool as u128,
+                fee as u128
+            );
+        }
+
+        #[test]
+        fn split_monthly_prize_conserves_lamports(prize in any::<u64>()) {
+            let shares = split_monthly_prize(prize);
+            prop_assert_eq!(shares.iter().map(|share| *share as u128).sum::<u128>(), prize as u128);
+            prop_assert!(shares[0] >= shares[1] && shares[1] >= shares[2]);
+        }
+    }
+
+    fn player(seed: u8) -> Pubkey {
+        Pubkey::new_from_array([seed; 32])
+    }
+
+    #[test]
+    fn ranking_is_bounded_and_sorted() {
+        let mut table = Vec::new();
+        update_ranking(&mut table, 3, player(1), 5);
+        update_ranking(&mut table, 3, player(2), 7);
+        update_ranking(&mut table, 3, player(3), 5);
+        assert_eq!(table.iter().map(|entry| entry.user).collect::<Vec<_>>(), [player(2), player(1), player(3)]);
+
+        // A full table only admits players who beat last place
+        update_ranking(&mut table, 3, player(4), 5);
+        assert!(table.iter().all(|entry| entry.user != player(4)));
+        update_ranking(&mut table, 3, player(4), 6);
+        assert_eq!(table.iter().map(|entry| entry.user).collect::<Vec<_>>(), [player(2), player(4), player(1)]);
+
+        // Existing players move up without duplicating
+        update_ranking(&mut table, 3, player(1), 9);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table[0], RankEntry { user: player(1), tickets: 9 });
+    }
+
+    #[test]
+    fn rankings_freeze_finished_periods() {
+        const DAY: i64 = SECONDS_PER_DAY;
+        // Monday 1 January 2024 starts both a week and a month
+        let start = days_from_civil(2024, 1, 1) * DAY;
+        let mut rankings = Rankings {
+            week: get_week_number(start),
+            weekly: Vec::new(),
+            month: get_month_number(start),
+            monthly: Vec::new(),
+            previous_week: 0,
+            previous_weekly_winner: RankEntry::default(),
+            previous_month: 0,
+            previous_monthly_winners: Default::default(),
+        };
+
+        rankings.record(player(1), 3, 3, start);
+        rankings.record(player(2), 4, 4, start);
+
+        // The next week freezes the weekly winner but the month is still running
+        rankings.roll(start + 7 * DAY);
+        assert_eq!(rankings.previous_weekly_winner, RankEntry { user: player(2), tickets: 4 });
+        assert!(rankings.weekly.is_empty());
+        assert_eq!(rankings.monthly.len(), 2);
+
+        // A later week without purchases keeps the frozen winner
+        rankings.roll(start + 14 * DAY);
+        assert_eq!(rankings.previous_week, get_week_number(start));
+
+        rankings.roll(start + 45 * DAY);
+        assert_eq!(rankings.previous_month, get_month_number(start));
+        assert_eq!(rankings.previous_monthly_winners[0].user, player(2));
+        assert_eq!(rankings.previous_monthly_winners[1].user, player(1));
+        assert_eq!(rankings.previous_monthly_winners[2], RankEntry::default());
+    }
+
+    #[test]
+    fn submitted_stats_only_count_the_running_period() {
+        const DAY: i64 = SECONDS_PER_DAY;
+        let start = days_from_civil(2024, 1, 1) * DAY;
+        let mut stats = UserStats::try_deserialize_unchecked(&mut &[0u8; UserStats::SPACE][..]).unwrap();
+        set_up_user_stats(&mut stats, player(1), start);
+        record_ticket_purchase(&mut stats, 5, 0, true, start);
+
+        assert_eq!(current_period_tickets(&stats, start + DAY), (5, 5));
+        // A submission in the next week must not carry last week's tickets over
+        assert_eq!(current_period_tickets(&stats, start + 7 * DAY), (0, 5));
+        assert_eq!(current_period_tickets(&stats, start + 31 * DAY), (0, 0));
+
+        // Players without tickets in a period are not added to its table
+        let mut table = Vec::new();
+        update_ranking(&mut table, WEEKLY_TOP_PLAYERS, player(1), 0);
+        assert!(table.is_empty());
+    }
+
+    fn timestamp(year: i64, month: u32, day: u32) -> i64 {
+        days_from_civil(year, month, day) * SECONDS_PER_DAY
+    }
+
+    #[test]
+    fn civil_dates_of_known_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(20_744), (2026, 10, 18));
+        assert_eq!(days_from_civil(2100, 3, 1) - days_from_civil(2100, 2, 28), 1);
+    }
+
+    #[test]
+    fn month_numbers_follow_the_calendar() {
+        assert_eq!(get_month_number(0), 197_001);
+        assert_eq!(get_month_number(timestamp(2024, 1, 31) + SECONDS_PER_DAY - 1), 202_401);
+        assert_eq!(get_month_number(timestamp(2024, 2, 1)), 202_402);
+        assert_eq!(get_month_number(timestamp(2024, 2, 29)), 202_402);
+        assert_eq!(get_month_number(timestamp(2024, 12, 31)), 202_412);
+        assert_eq!(get_month_number(timestamp(2025, 1, 1)), 202_501);
+    }
+
+    #[test]
+    fn week_numbers_follow_iso_8601() {
+        // Sunday 2026-10-18 is the last day of 2026-W42
+        assert_eq!(get_week_number(timestamp(2026, 10, 18)), 202_642);
+        assert_eq!(get_week_number(timestamp(2026, 10, 19)), 202_643);
+        // Week 1 is the week with the year's first Thursday
+        assert_eq!(get_week_number(timestamp(2024, 12, 30)), 202_501);
+        assert_eq!(get_week_number(timestamp(2021, 1, 3)), 202_053);
+        assert_eq!(get_week_number(timestamp(2021, 1, 4)), 202_101);
+        assert_eq!(get_week_number(0), 197_001);
+    }
+
+    #[test]
+    fn legacy_period_numbers_are_migrated() {
+        // Legacy week 2_900 started on 2025-07-31 (a Thursday), in 2025-W31
+        assert_eq!(migrate_week_number(2_900), get_week_number(timestamp(2025, 7, 31)));
+        assert_eq!(migrate_week_number(2_900), 202_531);
+        // Legacy month 676 started on 2025-07-11 (676 * 30 days after the epoch)
+        assert_eq!(migrate_month_number(676), 202_507);
+
+        assert_eq!(migrate_week_number(202_642), 202_642);
+        assert_eq!(migrate_month_number(202_610), 202_610);
+    }
+
+    proptest! {
+        #[test]
+        fn civil_conversion_round_trips(days in -1_000_000i64..1_000_000) {
+            let (year, month, day) = civil_from_days(days);
+            prop_assert!((1..=12).contains(&month) && (1..=31).contains(&day));
+            prop_assert_eq!(days_from_civil(year, month, day), days);
+        }
+
+        #[test]
+        fn periods_only_change_on_their_first_day(days in 0i64..200_000) {
+            let today = days * SECONDS_PER_DAY;
+            let tomorrow = today + SECONDS_PER_DAY;
+
+            let (_, _, day) = civil_from_days(days + 1);
+            prop_assert_eq!(get_month_number(today) != get_month_number(tomorrow), day == 1);
+
+            let tomorrow_is_monday = (days + 1 + 3).rem_euclid(7) == 0;
+            prop_assert_eq!(get_week_number(today) != get_week_number(tomorrow), tomorrow_is_monday);
+            prop_assert!(get_week_number(today) <= get_week_number(tomorrow));
+        }
+    }
+
+    // A raffle with every appended field at its largest
+    fn maximal_appended_fields(raffle: &mut Raffle) {
+        raffle.winning_index = Some(u32::MAX);
+        raffle.fee_bps = Some(BPS_DENOMINATOR);
+        raffle.prize_split_bps = vec![1_000; MAX_WINNERS];
+        raffle.winners = (1..=MAX_WINNERS as u32).collect();
+    }
+
+    #[test]
+    fn appended_space_covers_every_appended_field() {
+        let mut raffle = sold_raffle(0, &[]);
+        maximal_appended_fields(&mut raffle);
+        let mut data = Vec::new();
+        raffle.try_serialize(&mut data).unwrap();
+
+        // The same raffle in the original layout
+        let mut rest = &data[8..];
+        LegacyRaffle::deserialize(&mut rest).unwrap();
+        assert_eq!(rest.len(), Raffle::APPENDED_SPACE);
+    }
+
+    #[test]
+    fn raffle_space_leaves_room_for_the_draw() {
+        let params = RaffleParams {
+            name: "n".repeat(MAX_NAME_LEN),
+            description: "d".repeat(MAX_DESCRIPTION_LEN),
+            ticket_price: 1,
+            duration_hours: 1,
+            max_tickets: MAX_BITMAP_SIZE as u32,
+            commitment: Some([1; 32]),
+            min_tickets: Some(MAX_WINNERS as u32),
+            max_tickets_per_wallet: None,
+            fixed_prize: Some(u64::MAX),
+            sales_share_bps: Some(BPS_DENOMINATOR),
+            prize_split_bps: Some(vec![1_000; MAX_WINNERS]),
+        };
+        let mut raffle = sold_raffle(params.max_tickets, &(1..=MAX_BITMAP_SIZE as u32).collect::<Vec<_>>());
+        raffle.name = params.name.clone();
+        raffle.description = params.description.clone();
+        raffle.raffle_id = format!("7F-{}-{}", "S".repeat(MAX_SYMBOL_LEN), u32::MAX);
+        raffle.fee_bps = Some(BPS_DENOMINATOR);
+        raffle.prize_split_bps = params.prize_split_bps.clone().unwrap();
+
+        // The largest raffle still fits once all of its winners are drawn
+        let space = Raffle::space(&params);
+        record_draw(&mut raffle, [3; 32]).unwrap();
+        assert_eq!(raffle.winners.len(), MAX_WINNERS);
+        let mut data = vec![0u8; space];
+        raffle.try_serialize(&mut &mut data[..]).unwrap();
+        assert_eq!(raffle.try_to_vec().unwrap().len() + 8, space);
+
+        // Smaller raffles only pay for their own name, description and ticket bitmap
+        let small = RaffleParams { name: "Launch".to_string(), description: String::new(), max_tickets: 8, ..params };
+        assert_eq!(space - Raffle::space(&small), MAX_NAME_LEN - 6 + MAX_DESCRIPTION_LEN + (MAX_BITMAP_SIZE - 8) / BITS_PER_BYTE);
+    }
+
+    #[test]
+    fn maximal_baseline_raffle_decodes_after_upgrade() {
+        // Original raffles were created with 8 + size_of + 500 bytes; fill one completely
+        let space = 8 + size_of::<LegacyRaffle>() + 500;
+        let mut legacy = LegacyRaffle {
+            authority: player(1),
+            name: "Mega raffle".to_string(),
+            description: String::new(),
+            ticket_price: 1_000_000,
+            start_timestamp: 10,
+            end_timestamp: 20,
+            max_tickets: 2_000,
+            total_tickets: 3,
+            is_active: true,
+            winner: None,
+            raffle_id: "7F-SOL-001".to_string(),
+            used_numbers: vec![0u8; 250],
+            unique_entrants: 2,
+        };
+        mark_ticket_used(&mut legacy.used_numbers, 1_999);
+        let mut data = Raffle::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        legacy.description = "d".repeat(space - data.len());
+        data.truncate(Raffle::DISCRIMINATOR.len());
+        legacy.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), space);
+
+        // Nothing is left over for the appended fields
+        assert!(Raffle::try_deserialize(&mut &data[..]).is_err());
+
+        let upgraded_space = Raffle::upgraded_space(&data).unwrap();
+        assert_eq!(upgraded_space, space + Raffle::APPENDED_SPACE);
+        data.resize(upgraded_space, 0);
+
+        let mut raffle = Raffle::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!((raffle.name.as_str(), raffle.description.len()), ("Mega raffle", legacy.description.len()));
+        assert_eq!((raffle.total_tickets, raffle.unique_entrants), (3, 2));
+        assert!(is_ticket_used(&raffle.used_numbers, 1_999));
+        assert_eq!(raffle.current_status(), RaffleStatus::Active);
+        assert_eq!((raffle.fee_bps, raffle.index, raffle.mint), (None, 0, Pubkey::default()));
+
+        // Every appended field fits at its largest, and upgrading again changes nothing
+        maximal_appended_fields(&mut raffle);
+        raffle.try_serialize(&mut &mut data[..]).unwrap();
+        assert_eq!(Raffle::upgraded_space(&data).unwrap(), upgraded_space);
+    }
+
+    #[test]
+    fn legacy_user_stats_decode_after_growing() {
+        let legacy = UserStats {
+            user: player(7),
+            total_tickets_purchased: 12,
+            weekly_tickets: 3,
+            monthly_tickets: 9,
+            current_week: 2_964,
+            current_month: 691,
+            version: 0,
+            total_spent: 0,
+            raffles_entered: 0,
+            raffles_won: 0,
+            total_won: 0,
+            biggest_win: 0,
+            last_activity: 0,
+        };
+
+        // Version 0 accounts hold the discriminator and the six original fields (60 bytes)
+        let mut data = Vec::new();
+        legacy.try_serialize(&mut data).unwrap();
+        data.truncate(8 + 32 + 5 * 4);
+
+        // Growing with zeroes keeps every original field and zeroes the new ones
+        data.resize(UserStats::SPACE, 0);
+        let grown = UserStats::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(grown.user, legacy.user);
+        assert_eq!(grown.total_tickets_purchased, 12);
+        assert_eq!((grown.current_week, grown.current_month), (2_964, 691));
+        assert_eq!((grown.version, grown.total_spent, grown.last_activity), (0, 0, 0));
+    }
+
+    #[test]
+    fn claimed_legacy_raffles_cannot_pay_twice() {
+        let legacy = LegacyRaffle {
+            authority: player(1),
+            name: "Launch".to_string(),
+            description: String::new(),
+            ticket_price: 100,
+            start_timestamp: 10,
+            end_timestamp: 20,
+            max_tickets: 8,
+            total_tickets: 5,
+            is_active: false,
+            winner: Some(3),
+            raffle_id: "7F-SOL-001".to_string(),
+            used_numbers: vec![0b1_1111],
+            unique_entrants: 2,
+        };
+        let mut data = Raffle::DISCRIMINATOR.to_vec();
+        legacy.serialize(&mut data).unwrap();
+        data.resize(data.len() + Raffle::APPENDED_SPACE, 0);
+        let mut raffle = Raffle::try_deserialize(&mut &data[..]).unwrap();
+
+        // Decodes as drawn and unclaimed, but a paid-out raffle only holds its rent
+        assert_eq!((raffle.current_status(), raffle.claimed_places), (RaffleStatus::Drawn, 0));
+        assert!(!holds_legacy_pot(1_000, 1_000, 500));
+        assert!(holds_legacy_pot(1_500, 1_000, 500));
+
+        let mut history = RaffleHistory::try_deserialize_unchecked(&mut &[0u8; 512][..]).unwrap();
+        history.raffle_id = "7F-SOL-002".to_string();
+        history.winner_ticket = 3;
+        assert_eq!(mark_claimed_from_history(&mut raffle, &history), Err(RaffleError::HistoryRecordMismatch.into()));
+
+        history.raffle_id = raffle.raffle_id.clone();
+        mark_claimed_from_history(&mut raffle, &history).unwrap();
+        assert_eq!(raffle.current_status(), RaffleStatus::Claimed);
+        assert!(raffle.all_prizes_claimed());
+        assert_eq!(mark_claimed_from_history(&mut raffle, &history), Err(RaffleError::PrizeAlreadyClaimed.into()));
+    }
+
+    #[test]
+    fn history_holds_every_place_of_a_raffle_with_the_longest_name() {
+        let mut raffle = sold_raffle(MAX_WINNERS as u32, &(1..=MAX_WINNERS as u32).collect::<Vec<_>>());
+        raffle.name = "n".repeat(MAX_NAME_LEN);
+        raffle.raffle_id = format!("7F-{}-{}", "S".repeat(MAX_SYMBOL_LEN), u32::MAX);
+        assert_eq!(raffle.raffle_id.len(), MAX_RAFFLE_ID_LEN);
+        raffle.prize_split_bps = vec![1_000; MAX_WINNERS];
+        raffle.winning_index = Some(u32::MAX);
+        record_draw(&mut raffle, [7; 32]).unwrap();
+
+        let mut history = RaffleHistory::try_deserialize_unchecked(&mut &[0u8; RaffleHistory::SPACE][..]).unwrap();
+        for (place, ticket) in raffle.winning_tickets().into_iter().enumerate() {
+            let record = WinnerRecord { place: place as u8, ticket, winner: player(place as u8), amount: u64::MAX / 16, claim_slot: u64::MAX };
+            history.record_claim(&raffle, player(99), record, place == 0, u64::MAX, i64::MAX);
+
+            // Every claim has to fit the account created by the first one
+            let mut data = [0u8; RaffleHistory::SPACE];
+            history.try_serialize(&mut &mut data[..]).unwrap();
+        }
+        assert_eq!(history.winners.len(), MAX_WINNERS);
+        assert_eq!(history.try_to_vec().unwrap().len() + 8, RaffleHistory::SPACE);
+    }
+
+    #[test]
+    fn history_names_are_cut_to_the_record_size() {
+        let record = WinnerRecord { place: 9, ticket: u32::MAX, winner: player(1), amount: u64::MAX, claim_slot: u64::MAX };
+        assert_eq!(record.try_to_vec().unwrap().len(), WinnerRecord::SIZE);
+
+        assert_eq!(truncate_text("Launch", MAX_NAME_LEN), "Launch");
+        assert_eq!(truncate_text(&"a".repeat(100), MAX_NAME_LEN).len(), MAX_NAME_LEN);
+        // Never splits a multi-byte character
+        let name = format!("{}é", "a".repeat(MAX_NAME_LEN - 1));
+        assert_eq!(truncate_text(&name, MAX_NAME_LEN), "a".repeat(MAX_NAME_LEN - 1));
+    }
+
+    #[test]
+    fn legacy_history_records_decode() {
+        // Original layout: ... claim_timestamp, then an empty transaction signature string,
+        // followed by the zeroed padding the account was created with
+        let mut data = RaffleHistory::DISCRIMINATOR.to_vec();
+        "7F-SOL-001".to_string().serialize(&mut data).unwrap();
+        "Launch".to_string().serialize(&mut data).unwrap();
+        (10i64, 20i64, 5u32, 100u32, 950u64, 3u32).serialize(&mut data).unwrap();
+        (player(3), 30i64, String::new()).serialize(&mut data).unwrap();
+        data.resize(data.len() + 300, 0);
+
+        let history = RaffleHistory::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(history.raffle_id, "7F-SOL-001");
+        assert_eq!(history.winner_address, player(3));
+        assert_eq!(history.claim_timestamp, 30);
+        assert_eq!((history.claim_slot, history.platform_fee, history.winning_index), (0, 0, None));
+        assert_eq!((history.raffle, history.prize_mint), (Pubkey::default(), Pubkey::default()));
+        assert!(history.winners.is_empty());
+    }
+
+    #[test]
+    fn wins_update_lifetime_stats() {
+        let mut stats = UserStats::try_deserialize_unchecked(&mut &[0u8; UserStats::SPACE][..]).unwrap();
+        record_win(&mut stats, 500, true, 10);
+        record_win(&mut stats, 200, true, 20);
+        assert_eq!((stats.raffles_won, stats.total_won, stats.biggest_win, stats.last_activity), (2, 700, 500, 20));
+
+        // A second place claimed in the same raffle adds its prize but not another win
+        record_win(&mut stats, 100, false, 30);
+        assert_eq!((stats.raffles_won, stats.total_won, stats.biggest_win), (2, 800, 500));
+    }
+
+    #[test]
+    fn mint_symbols_are_short_upper_case_tickers() {
+        assert!(is_valid_symbol("USDC"));
+        assert!(is_valid_symbol("BONK"));
+        assert!(is_valid_symbol("W2"));
+        assert!(!is_valid_symbol(""));
+        assert!(!is_valid_symbol("usdc"));
+        assert!(!is_valid_symbol("US-DC"));
+        assert!(!is_valid_symbol("ABCDEFGHIJK"));
+        assert!(!is_valid_symbol("SOL"));
+    }
+
+    #[test]
+    fn only_safe_mint_extensions_are_supported() {
+        assert!(supports_mint_extensions(&[]));
+        assert!(supports_mint_extensions(&[ExtensionType::TransferFeeConfig, ExtensionType::MetadataPointer, ExtensionType::TokenMetadata]));
+        assert!(!supports_mint_extensions(&[ExtensionType::TransferHook]));
+        assert!(!supports_mint_extensions(&[ExtensionType::PermanentDelegate]));
+        assert!(!supports_mint_extensions(&[ExtensionType::TransferFeeConfig, ExtensionType::DefaultAccountState]));
+        assert!(!supports_mint_extensions(&[ExtensionType::NonTransferable]));
+    }
+
+    #[test]
+    fn prize_splits_add_up_to_the_whole_prize() {
+        assert!(is_valid_prize_split(&[10_000]));
+        assert!(is_valid_prize_split(&[5_000, 3_000, 2_000]));
+        assert!(!is_valid_prize_split(&[]));
+        assert!(!is_valid_prize_split(&[5_000, 3_000]));
+        assert!(!is_valid_prize_split(&[10_000, 0]));
+        assert!(!is_valid_prize_split(&[1_000; 11]));
+
+        assert_eq!(split_prize_tiers(1_000, &[5_000, 3_000, 2_000]), vec![500, 300, 200]);
+        // 50/30/20 of 999 is 499.5/299.7/199.8: the 2 lamports rounded off go to first place
+        assert_eq!(split_prize_tiers(999, &[5_000, 3_000, 2_000]), vec![501, 299, 199]);
+        assert_eq!(split_prize_tiers(999, &[10_000]), vec![999]);
+    }
+
+    proptest! {
+        #[test]
+        fn split_prize_tiers_conserves_the_prize(prize in any::<u64>(), shares in proptest::collection::vec(1..=1_000u16, 1..=MAX_WINNERS)) {
+            let shares = split_prize_tiers(prize, &shares);
+            prop_assert_eq!(shares.iter().map(|share| *share as u128).sum::<u128>(), prize as u128);
+        }
+
+        #[test]
+        fn winners_are_distinct_sold_tickets(
+            sold in proptest::collection::btree_set(1..=64u32, 1..=64),
+            randomness in any::<[u8; 32]>(),
+            count in 1..=MAX_WINNERS,
+        ) {
+            let mut used_numbers = vec![0u8; 8];
+            for number in &sold {
+                mark_ticket_used(&mut used_numbers, *number);
+            }
+            let total = sold.len() as u32;
+
+            match draw_winning_tickets(&used_numbers, 64, total, &randomness, count) {
+                Some(winners) => {
+                    prop_assert_eq!(winners.len(), count);
+                    prop_assert!(winners.iter().all(|ticket| sold.contains(ticket)));
+                    let distinct: std::collections::BTreeSet<_> = winners.iter().collect();
+                    prop_assert_eq!(distinct.len(), count);
+                    // First place is the single-winner draw
+                    let index = winning_index_from_randomness(&randomness, total);
+                    prop_assert_eq!(Some(winners[0]), nth_sold_ticket(&used_numbers, 64, index));
+                }
+                None => prop_assert!(count > sold.len()),
+            }
+        }
+    }
+
+    #[test]
+    fn split_sales_examples() {
+        assert_eq!(split_sales(1_000, 2_500), SalesSplit { winner_share: 250, creator_share: 750 });
+        assert_eq!(split_sales(999, 5_000), SalesSplit { winner_share: 499, creator_share: 500 });
+        assert_eq!(split_sales(1_000, 0), SalesSplit { winner_share: 0, creator_share: 1_000 });
+        assert_eq!(split_sales(1_000, BPS_DENOMINATOR), SalesSplit { winner_share: 1_000, creator_share: 0 });
+    }
+
+    proptest! {
+        #[test]
+        fn split_sales_conserves_sales(sales in any::<u64>(), share_bps in any::<u16>()) {
+            let split = split_sales(sales, share_bps);
+            prop_assert_eq!(split.winner_share + split.creator_share, sales);
+        }
+    }
+
+    #[test]
+    fn split_platform_fee_examples() {
+        assert_eq!(split_platform_fee(1_000, 1_000, 500), FeeSplit { treasury: 850, weekly_pool: 100, monthly_pool: 50 });
+        // 10% and 5% of 999 are 99.9 and 49.95: pools round down, the treasury keeps the rest
+        assert_eq!(split_platform_fee(999, 1_000, 500), FeeSplit { treasury: 851, weekly_pool: 99, monthly_pool: 49 });
+        assert_eq!(split_platform_fee(1_000, 0, 0), FeeSplit { treasury: 1_000, weekly_pool: 0, monthly_pool: 0 });
+    }
+
+    #[test]
+    fn split_monthly_prize_examples() {
+        assert_eq!(split_monthly_prize(1_000), [600, 300, 100]);
+        // 60/30/10 of 999 is 599.4/299.7/99.9: the 2 lamports rounded off go to first place
+        assert_eq!(split_monthly_prize(999), [601, 299, 99]);
+        assert_eq!(split_monthly_prize(0), [0, 0, 0]);
+    }
 }
@@ -0,0 +1,58 @@
+use anchor_lang::prelude::*;
+
+declare_id!("5cxCVKqZiDn7Eqeedev9j4FXXK8cXuWpn8J4x1UzpYnv");
+
+// Stand-in for a VRF oracle on localnet: whoever created a randomness account can fulfil it with
+// any value. Point the raffle program at it with set_oracle_program; never use it on mainnet.
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    // Create a randomness account at round 0
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        let randomness = &mut ctx.accounts.randomness;
+        randomness.round = 0;
+        randomness.value = [0u8; 32];
+        randomness.authority = ctx.accounts.authority.key();
+
+        msg!("Mock randomness account {} created", randomness.key());
+        Ok(())
+    }
+
+    // Write the next round of randomness
+    pub fn fulfill(ctx: Context<Fulfill>, value: [u8; 32]) -> Result<()> {
+        let randomness = &mut ctx.accounts.randomness;
+        randomness.round += 1;
+        randomness.value = value;
+
+        msg!("Mock randomness round {} fulfilled", randomness.round);
+        Ok(())
+    }
+}
+
+// Layout read by the raffle program: discriminator, round (u64 LE), value ([u8; 32])
+#[account]
+pub struct OracleRandomness {
+    pub round: u64,                // Incremented on every fulfilment
+    pub value: [u8; 32],           // Latest randomness value
+    pub authority: Pubkey,         // Allowed to fulfil
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = authority, space = 8 + std::mem::size_of::<OracleRandomness>())]
+    pub randomness: Account<'info, OracleRandomness>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Fulfill<'info> {
+    #[account(mut, has_one = authority)]
+    pub randomness: Account<'info, OracleRandomness>,
+
+    pub authority: Signer<'info>,
+}